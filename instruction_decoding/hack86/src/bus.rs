@@ -0,0 +1,98 @@
+use std::any::Any;
+use std::ops::{Range, RangeInclusive};
+
+// Hardware attached to the bus. A device claims a range of physical addresses,
+// a range of I/O ports or both, and the CPU's accesses there go to the device
+// instead of RAM.
+// Writes to device memory are journaled like RAM, but port I/O is not: stepping
+// back does not undo `output`.
+pub trait Device: Any {
+    fn memory_range(&self) -> Option<Range<usize>> {
+        None
+    }
+
+    fn ports(&self) -> Option<RangeInclusive<u16>> {
+        None
+    }
+
+    // `offset` is relative to the start of `memory_range`.
+    fn read(&self, _offset: usize) -> u8 {
+        0xFF
+    }
+
+    fn write(&mut self, _offset: usize, _value: u8) {}
+
+    fn input(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, _port: u16, _value: u8) {}
+}
+
+// Routes memory and port accesses to the attached devices. When two devices
+// claim the same address or port, the one attached first wins.
+#[derive(Default)]
+pub struct Bus {
+    devices: Vec<Box<dyn Device>>,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus::default()
+    }
+
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.devices.push(device);
+    }
+
+    pub fn device<T: Device>(&self) -> Option<&T> {
+        self.devices
+            .iter()
+            .find_map(|device| (device.as_ref() as &dyn Any).downcast_ref::<T>())
+    }
+
+    // Nothing answers on an unclaimed port, so the data lines float high.
+    pub fn input(&mut self, port: u16) -> u8 {
+        match self.port_device(port) {
+            Some(device) => device.input(port),
+            None => 0xFF,
+        }
+    }
+
+    pub fn output(&mut self, port: u16, value: u8) {
+        if let Some(device) = self.port_device(port) {
+            device.output(port, value);
+        }
+    }
+
+    // None when the address is plain RAM.
+    pub(crate) fn read(&self, address: usize) -> Option<u8> {
+        self.devices.iter().find_map(|device| {
+            let range = device.memory_range()?;
+            range
+                .contains(&address)
+                .then(|| device.read(address - range.start))
+        })
+    }
+
+    // False when the address is plain RAM.
+    pub(crate) fn write(&mut self, address: usize, value: u8) -> bool {
+        let device = self.devices.iter_mut().find_map(|device| {
+            let range = device.memory_range()?;
+            range.contains(&address).then_some((device, range.start))
+        });
+        match device {
+            Some((device, start)) => {
+                device.write(address - start, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn port_device(&mut self, port: u16) -> Option<&mut Box<dyn Device>> {
+        self.devices
+            .iter_mut()
+            .find(|device| device.ports().is_some_and(|ports| ports.contains(&port)))
+    }
+}
@@ -0,0 +1,529 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+use crate::decode::{InstructionCategory, Mnemonic, RegisterName};
+use crate::encode;
+use crate::memory::physical_address;
+use crate::simulate::{Flags, Hack86};
+
+// How many already executed instructions `list` shows before the current one.
+const HISTORY_LENGTH: usize = 4;
+
+// How many instructions can be stepped back over, and how much memory their
+// history may take.
+const JOURNAL_CAPACITY: usize = 100_000;
+const JOURNAL_BYTES: usize = 64 << 20;
+
+const HELP: &str = "\
+s, step [n]            execute n instructions (default 1)
+n, next                step over calls and interrupts
+c, continue            run until a breakpoint, watchpoint or the end
+rs, reverse-step [n]   undo the last n instructions (default 1)
+g, goto <count>        go back or forward to after `count` instructions
+u, until <address>     run until CS:IP reaches the address
+b, break <address>     stop when CS:IP reaches the address
+w, watch <register>    stop when the register changes
+w, watch <address> [n] stop when any of n bytes (default 1) change
+i, info                list breakpoints and watchpoints
+d, delete <number>     remove a breakpoint or watchpoint
+r, registers           show registers and flags
+m, memory <address> [n] dump n bytes of memory (default 64)
+l, list [n]            disassemble around IP, n instructions ahead (default 5)
+set <register> <value> change a register, `ip` or `flags`
+q, quit                stop debugging
+
+Addresses are `segment:offset` or just an offset, which is taken relative to
+CS for code and DS for data. Numbers are decimal, `0x` or `h` suffixed hex,
+and register names stand for their current value.";
+
+enum Stop {
+    Breakpoint {
+        segment: u16,
+        offset: u16,
+    },
+    Memory {
+        segment: u16,
+        offset: u16,
+        contents: Vec<u8>,
+    },
+    Register {
+        name: RegisterName,
+        value: u16,
+    },
+}
+
+pub struct Debugger {
+    computer: Hack86,
+    // Numbered in creation order; numbers are not reused after a delete.
+    stops: Vec<(usize, Stop)>,
+    next_stop: usize,
+    history: VecDeque<(u16, u16)>,
+}
+
+impl Debugger {
+    pub fn new(mut computer: Hack86) -> Debugger {
+        computer.enable_journal(JOURNAL_CAPACITY, JOURNAL_BYTES);
+        Debugger {
+            computer,
+            stops: Vec::new(),
+            next_stop: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn into_computer(self) -> Hack86 {
+        self.computer
+    }
+
+    pub fn run(&mut self) {
+        let stdin = std::io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut previous = String::new();
+        self.list(0);
+        loop {
+            print!(
+                "({:04x}:{:04x} #{}) ",
+                self.cpu_segment(),
+                self.computer.cpu().ip(),
+                self.computer.instruction_count()
+            );
+            std::io::stdout().flush().expect("failed to flush stdout");
+            let Some(Ok(line)) = lines.next() else {
+                println!();
+                return;
+            };
+            // An empty line repeats the previous command, so stepping is just
+            // a matter of pressing enter.
+            let line = if line.trim().is_empty() {
+                previous.clone()
+            } else {
+                line
+            };
+            let words = line.split_whitespace().collect::<Vec<&str>>();
+            let Some((&command, arguments)) = words.split_first() else {
+                continue;
+            };
+            previous = line.clone();
+
+            let result = match command {
+                "s" | "step" => self.step_command(arguments),
+                "n" | "next" => self.next(),
+                "c" | "continue" => self.execute(usize::MAX, None),
+                "rs" | "reverse-step" => self.reverse_step(arguments),
+                "g" | "goto" => self.goto(arguments),
+                "u" | "until" => self.until(arguments),
+                "b" | "break" => self.add_breakpoint(arguments),
+                "w" | "watch" => self.add_watchpoint(arguments),
+                "i" | "info" => self.info(),
+                "d" | "delete" => self.delete(arguments),
+                "r" | "registers" => {
+                    println!("{}", self.computer.cpu());
+                    Ok(())
+                }
+                "m" | "memory" => self.dump(arguments),
+                "l" | "list" => self.list_command(arguments),
+                "set" => self.set(arguments),
+                "h" | "help" => {
+                    println!("{}", HELP);
+                    Ok(())
+                }
+                "q" | "quit" => return,
+                _ => Err(format!("unknown command `{}`, try `help`", command)),
+            };
+            if let Err(message) = result {
+                println!("{}", message);
+            }
+        }
+    }
+
+    fn step_command(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let count = match arguments {
+            [] => 1,
+            [count] => usize::from(self.value(count)?),
+            _ => return Err("usage: step [n]".to_string()),
+        };
+        self.execute(count, None)
+    }
+
+    fn next(&mut self) -> Result<(), String> {
+        let (segment, ip) = (self.cpu_segment(), self.computer.cpu().ip());
+        let instruction = self
+            .computer
+            .decode_at(segment, ip)
+            .map_err(|error| error.to_string())?;
+        let steps_into = matches!(
+            instruction.instruction_category,
+            InstructionCategory::NearJump(Mnemonic::CALL, _)
+                | InstructionCategory::FarJump(Mnemonic::CALL, _, _)
+                | InstructionCategory::IndirectFar(Mnemonic::CALL, _)
+                | InstructionCategory::SingleOperand(Mnemonic::CALL, _, _)
+                | InstructionCategory::Immediate(Mnemonic::INT, _)
+                | InstructionCategory::NoOperands(Mnemonic::INT3 | Mnemonic::INTO)
+        );
+        if steps_into {
+            let return_address = (segment, ip.wrapping_add(u16::from(instruction.length)));
+            self.execute(usize::MAX, Some(return_address))
+        } else {
+            self.execute(1, None)
+        }
+    }
+
+    fn reverse_step(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let count = match arguments {
+            [] => 1,
+            [count] => usize::from(self.value(count)?),
+            _ => return Err("usage: reverse-step [n]".to_string()),
+        };
+        for _ in 0..count {
+            if !self.computer.step_back() {
+                return Err("no earlier history recorded".to_string());
+            }
+            self.history.pop_back();
+        }
+        self.list(0);
+        Ok(())
+    }
+
+    fn goto(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let [count] = arguments else {
+            return Err("usage: goto <count>".to_string());
+        };
+        let count = count
+            .parse()
+            .map_err(|_| format!("invalid instruction count `{}`", count))?;
+        // Executed addresses are only known for steps taken from here on.
+        self.history.clear();
+        let result = self.computer.seek(count).map_err(|error| error.to_string());
+        self.list(0);
+        result
+    }
+
+    fn until(&mut self, arguments: &[&str]) -> Result<(), String> {
+        match arguments {
+            [address] => {
+                let target = self.address(address, RegisterName::CS)?;
+                self.execute(usize::MAX, Some(target))
+            }
+            _ => Err("usage: until <address>".to_string()),
+        }
+    }
+
+    // Runs at most `limit` instructions, stopping early at `target`, a
+    // breakpoint, a triggered watchpoint or the end of the program.
+    fn execute(&mut self, limit: usize, target: Option<(u16, u16)>) -> Result<(), String> {
+        for _ in 0..limit {
+            if !self.computer.running() {
+                break;
+            }
+
+            let address = (self.cpu_segment(), self.computer.cpu().ip());
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            self.history.push_back(address);
+            self.computer.step().map_err(|error| error.to_string())?;
+
+            let address = (self.cpu_segment(), self.computer.cpu().ip());
+            if self.check_stops(address) || Some(address) == target {
+                return Ok(());
+            }
+        }
+
+        if !self.computer.running() {
+            match self.computer.exit_code() {
+                Some(exit_code) => println!("program exited with code {}", exit_code),
+                None if self.computer.cpu().halted() => println!("program halted"),
+                None => println!("program finished"),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_stops(&mut self, (segment, ip): (u16, u16)) -> bool {
+        let mut stopped = false;
+        for (number, stop) in self.stops.iter_mut() {
+            match stop {
+                Stop::Breakpoint {
+                    segment: stop_segment,
+                    offset,
+                } => {
+                    if physical_address(*stop_segment, *offset) == physical_address(segment, ip) {
+                        println!("breakpoint {} at {:04x}:{:04x}", number, segment, ip);
+                        stopped = true;
+                    }
+                }
+                Stop::Memory {
+                    segment,
+                    offset,
+                    contents,
+                } => {
+                    let current = self.computer.read_memory(*segment, *offset, contents.len());
+                    if current != *contents {
+                        println!(
+                            "watchpoint {}: {:04x}:{:04x} {} -> {}",
+                            number,
+                            segment,
+                            offset,
+                            hex_bytes(contents),
+                            hex_bytes(&current)
+                        );
+                        *contents = current;
+                        stopped = true;
+                    }
+                }
+                Stop::Register { name, value } => {
+                    let current = self.computer.cpu().get_register(name);
+                    if current != *value {
+                        println!(
+                            "watchpoint {}: {} {:#x} -> {:#x}",
+                            number, name, value, current
+                        );
+                        *value = current;
+                        stopped = true;
+                    }
+                }
+            }
+        }
+        stopped
+    }
+
+    fn add_breakpoint(&mut self, arguments: &[&str]) -> Result<(), String> {
+        match arguments {
+            [address] => {
+                let (segment, offset) = self.address(address, RegisterName::CS)?;
+                let number = self.add_stop(Stop::Breakpoint { segment, offset });
+                println!("breakpoint {} at {:04x}:{:04x}", number, segment, offset);
+                Ok(())
+            }
+            _ => Err("usage: break <address>".to_string()),
+        }
+    }
+
+    fn add_watchpoint(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let register = match arguments {
+            [name] => encode::parse_register(name),
+            _ => None,
+        };
+        let stop = match (arguments, register) {
+            (_, Some(name)) => {
+                let value = self.computer.cpu().get_register(&name);
+                Stop::Register { name, value }
+            }
+            ([address] | [address, _], None) => {
+                let (segment, offset) = self.address(address, RegisterName::DS)?;
+                let length = match arguments {
+                    [_, length] => usize::from(self.value(length)?),
+                    _ => 1,
+                };
+                let contents = self.computer.read_memory(segment, offset, length);
+                Stop::Memory {
+                    segment,
+                    offset,
+                    contents,
+                }
+            }
+            _ => return Err("usage: watch <register> | watch <address> [n]".to_string()),
+        };
+        let number = self.add_stop(stop);
+        println!("watchpoint {}", number);
+        Ok(())
+    }
+
+    fn add_stop(&mut self, stop: Stop) -> usize {
+        let number = self.next_stop;
+        self.next_stop += 1;
+        self.stops.push((number, stop));
+        number
+    }
+
+    fn info(&self) -> Result<(), String> {
+        println!(
+            "{} instructions executed, the last {} can be undone",
+            self.computer.instruction_count(),
+            self.computer.journal_length()
+        );
+        if self.stops.is_empty() {
+            println!("no breakpoints or watchpoints");
+        }
+        for (number, stop) in &self.stops {
+            match stop {
+                Stop::Breakpoint { segment, offset } => {
+                    println!("{}: breakpoint at {:04x}:{:04x}", number, segment, offset)
+                }
+                Stop::Memory {
+                    segment,
+                    offset,
+                    contents,
+                } => println!(
+                    "{}: watchpoint on {} bytes at {:04x}:{:04x}",
+                    number,
+                    contents.len(),
+                    segment,
+                    offset
+                ),
+                Stop::Register { name, .. } => println!("{}: watchpoint on {}", number, name),
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, arguments: &[&str]) -> Result<(), String> {
+        match arguments {
+            [number] => {
+                let number = usize::from(self.value(number)?);
+                let index = self
+                    .stops
+                    .iter()
+                    .position(|(stop_number, _)| *stop_number == number)
+                    .ok_or_else(|| format!("no breakpoint or watchpoint {}", number))?;
+                self.stops.remove(index);
+                Ok(())
+            }
+            _ => Err("usage: delete <number>".to_string()),
+        }
+    }
+
+    fn dump(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let ((segment, offset), length) = match arguments {
+            [address] => (self.address(address, RegisterName::DS)?, 64),
+            [address, length] => (
+                self.address(address, RegisterName::DS)?,
+                usize::from(self.value(length)?),
+            ),
+            _ => return Err("usage: memory <address> [n]".to_string()),
+        };
+
+        let bytes = self.computer.read_memory(segment, offset, length);
+        for (row, chunk) in bytes.chunks(16).enumerate() {
+            let text = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        char::from(byte)
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            println!(
+                "{:04x}:{:04x}  {:<47}  {}",
+                segment,
+                offset.wrapping_add(row as u16 * 16),
+                hex_bytes(chunk),
+                text
+            );
+        }
+        Ok(())
+    }
+
+    fn list_command(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let count = match arguments {
+            [] => 5,
+            [count] => usize::from(self.value(count)?),
+            _ => return Err("usage: list [n]".to_string()),
+        };
+        self.list(count);
+        Ok(())
+    }
+
+    // x86 code cannot be reliably decoded backwards, so the instructions
+    // before IP are the ones we actually executed to get here.
+    fn list(&self, count: usize) {
+        for &(segment, offset) in &self.history {
+            self.list_instruction("  ", segment, offset);
+        }
+        let segment = self.cpu_segment();
+        let mut offset = self.computer.cpu().ip();
+        for index in 0..=count {
+            let marker = if index == 0 { "=>" } else { "  " };
+            match self.list_instruction(marker, segment, offset) {
+                Some(length) => offset = offset.wrapping_add(length),
+                None => break,
+            }
+        }
+    }
+
+    fn list_instruction(&self, marker: &str, segment: u16, offset: u16) -> Option<u16> {
+        match self.computer.decode_at(segment, offset) {
+            Ok(instruction) => {
+                let length = u16::from(instruction.length);
+                let bytes = self
+                    .computer
+                    .read_memory(segment, offset, usize::from(length));
+                println!(
+                    "{} {:04x}:{:04x}  {:<18}{}",
+                    marker,
+                    segment,
+                    offset,
+                    hex_bytes(&bytes),
+                    instruction
+                );
+                Some(length)
+            }
+            Err(error) => {
+                println!("{} {:04x}:{:04x}  {}", marker, segment, offset, error);
+                None
+            }
+        }
+    }
+
+    fn set(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let [name, value] = arguments else {
+            return Err("usage: set <register> <value>".to_string());
+        };
+        let value = self.value(value)?;
+        let cpu = self.computer.cpu_mut();
+        match name.to_ascii_lowercase().as_str() {
+            "ip" => cpu.set_ip(value),
+            "flags" => cpu.set_flags(Flags::from(value)),
+            _ => {
+                let name = encode::parse_register(name)
+                    .ok_or_else(|| format!("unknown register `{}`", name))?;
+                let old = cpu.get_register(&name);
+                cpu.set_register(&name, value);
+                println!(" {}:{:#x}->{:#x}", name, old, cpu.get_register(&name));
+            }
+        }
+        Ok(())
+    }
+
+    fn cpu_segment(&self) -> u16 {
+        self.computer.cpu().get_register(&RegisterName::CS)
+    }
+
+    fn address(&self, text: &str, default_segment: RegisterName) -> Result<(u16, u16), String> {
+        match text.split_once(':') {
+            Some((segment, offset)) => Ok((self.value(segment)?, self.value(offset)?)),
+            None => Ok((
+                self.computer.cpu().get_register(&default_segment),
+                self.value(text)?,
+            )),
+        }
+    }
+
+    fn value(&self, text: &str) -> Result<u16, String> {
+        if text.eq_ignore_ascii_case("ip") {
+            return Ok(self.computer.cpu().ip());
+        }
+        if let Some(name) = encode::parse_register(text) {
+            return Ok(self.computer.cpu().get_register(&name));
+        }
+        let lowercase = text.to_ascii_lowercase();
+        let parsed = if let Some(hex) = lowercase.strip_prefix("0x") {
+            u16::from_str_radix(hex, 16)
+        } else if let Some(hex) = lowercase.strip_suffix('h') {
+            u16::from_str_radix(hex, 16)
+        } else {
+            lowercase.parse()
+        };
+        parsed.map_err(|_| format!("invalid number `{}`", text))
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
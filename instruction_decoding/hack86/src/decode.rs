@@ -1,4 +1,5 @@
 #[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum Mode {
     MemoryModeNoDisplacement,
     MemoryMode8BitDisplacement,
@@ -6,7 +7,7 @@ enum Mode {
     RegisterMode,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RegisterName {
     AL,
     BL,
@@ -24,6 +25,10 @@ pub enum RegisterName {
     SP,
     DI,
     SI,
+    ES,
+    CS,
+    SS,
+    DS,
 }
 
 impl std::fmt::Display for RegisterName {
@@ -45,20 +50,68 @@ impl std::fmt::Display for RegisterName {
             RegisterName::BP => "bp",
             RegisterName::SI => "si",
             RegisterName::DI => "di",
+            RegisterName::ES => "es",
+            RegisterName::CS => "cs",
+            RegisterName::SS => "ss",
+            RegisterName::DS => "ds",
         };
         write!(f, "{}", s)
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum Mnemonic {
+impl RegisterName {
+    pub fn is_word(&self) -> bool {
+        !matches!(
+            self,
+            RegisterName::AL
+                | RegisterName::BL
+                | RegisterName::CL
+                | RegisterName::DL
+                | RegisterName::AH
+                | RegisterName::BH
+                | RegisterName::CH
+                | RegisterName::DH
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mnemonic {
+    AAA,
+    AAD,
+    AAM,
+    AAS,
+    ADC,
     ADD,
+    AND,
+    CALL,
+    CBW,
+    CLC,
+    CLD,
+    CLI,
+    CMC,
     CMP,
+    CMPS,
+    CWD,
+    DAA,
+    DAS,
+    DEC,
+    DIV,
+    HLT,
+    IDIV,
+    IMUL,
+    IN,
+    INC,
+    INT,
+    INT3,
+    INTO,
+    IRET,
     JA,
     JC,
     JCXZ,
     JG,
     JL,
+    JMP,
     JNA,
     JNC,
     JNG,
@@ -71,23 +124,88 @@ pub(crate) enum Mnemonic {
     JPO,
     JS,
     JZ,
+    LAHF,
+    LDS,
+    LEA,
+    LES,
+    LODS,
     LOOP,
     LOOPE,
     LOOPNE,
     MOV,
+    MOVS,
+    MUL,
+    NEG,
+    NOP,
+    NOT,
+    OR,
+    OUT,
+    POP,
+    POPF,
+    PUSH,
+    PUSHF,
+    RCL,
+    RCR,
+    RET,
+    RETF,
+    ROL,
+    ROR,
+    SAHF,
+    SAR,
+    SBB,
+    SCAS,
+    SHL,
+    SHR,
+    STC,
+    STD,
+    STI,
+    STOS,
     SUB,
+    TEST,
+    WAIT,
+    XCHG,
+    XLAT,
+    XOR,
 }
 
 impl std::fmt::Display for Mnemonic {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
+            Mnemonic::AAA => "aaa",
+            Mnemonic::AAD => "aad",
+            Mnemonic::AAM => "aam",
+            Mnemonic::AAS => "aas",
+            Mnemonic::ADC => "adc",
             Mnemonic::ADD => "add",
+            Mnemonic::AND => "and",
+            Mnemonic::CALL => "call",
+            Mnemonic::CBW => "cbw",
+            Mnemonic::CLC => "clc",
+            Mnemonic::CLD => "cld",
+            Mnemonic::CLI => "cli",
+            Mnemonic::CMC => "cmc",
             Mnemonic::CMP => "cmp",
+            Mnemonic::CMPS => "cmps",
+            Mnemonic::CWD => "cwd",
+            Mnemonic::DAA => "daa",
+            Mnemonic::DAS => "das",
+            Mnemonic::DEC => "dec",
+            Mnemonic::DIV => "div",
+            Mnemonic::HLT => "hlt",
+            Mnemonic::IDIV => "idiv",
+            Mnemonic::IMUL => "imul",
+            Mnemonic::IN => "in",
+            Mnemonic::INC => "inc",
+            Mnemonic::INT => "int",
+            Mnemonic::INT3 => "int3",
+            Mnemonic::INTO => "into",
+            Mnemonic::IRET => "iret",
             Mnemonic::JA => "ja",
             Mnemonic::JC => "jc",
             Mnemonic::JCXZ => "jcxz",
             Mnemonic::JG => "jg",
             Mnemonic::JL => "jl",
+            Mnemonic::JMP => "jmp",
             Mnemonic::JNA => "jna",
             Mnemonic::JNC => "jnc",
             Mnemonic::JNG => "jng",
@@ -100,37 +218,89 @@ impl std::fmt::Display for Mnemonic {
             Mnemonic::JPO => "jpo",
             Mnemonic::JS => "js",
             Mnemonic::JZ => "jz",
+            Mnemonic::LAHF => "lahf",
+            Mnemonic::LDS => "lds",
+            Mnemonic::LEA => "lea",
+            Mnemonic::LES => "les",
+            Mnemonic::LODS => "lods",
             Mnemonic::LOOP => "loop",
             Mnemonic::LOOPE => "loope",
             Mnemonic::LOOPNE => "loopne",
             Mnemonic::MOV => "mov",
+            Mnemonic::MOVS => "movs",
+            Mnemonic::MUL => "mul",
+            Mnemonic::NEG => "neg",
+            Mnemonic::NOP => "nop",
+            Mnemonic::NOT => "not",
+            Mnemonic::OR => "or",
+            Mnemonic::OUT => "out",
+            Mnemonic::POP => "pop",
+            Mnemonic::POPF => "popf",
+            Mnemonic::PUSH => "push",
+            Mnemonic::PUSHF => "pushf",
+            Mnemonic::RCL => "rcl",
+            Mnemonic::RCR => "rcr",
+            Mnemonic::RET => "ret",
+            Mnemonic::RETF => "retf",
+            Mnemonic::ROL => "rol",
+            Mnemonic::ROR => "ror",
+            Mnemonic::SAHF => "sahf",
+            Mnemonic::SAR => "sar",
+            Mnemonic::SBB => "sbb",
+            Mnemonic::SCAS => "scas",
+            Mnemonic::SHL => "shl",
+            Mnemonic::SHR => "shr",
+            Mnemonic::STC => "stc",
+            Mnemonic::STD => "std",
+            Mnemonic::STI => "sti",
+            Mnemonic::STOS => "stos",
             Mnemonic::SUB => "sub",
+            Mnemonic::TEST => "test",
+            Mnemonic::WAIT => "wait",
+            Mnemonic::XCHG => "xchg",
+            Mnemonic::XLAT => "xlat",
+            Mnemonic::XOR => "xor",
         };
         write!(f, "{}", s)
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum RegisterMemory {
+/// A ModRM operand: a register or one of the 8086 addressing modes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegisterMemory {
     Register(RegisterName),
     RegisterAddress(RegisterName),
-    RegisterAddressDisplacement(RegisterName, u16),
+    RegisterAddressDisplacement(RegisterName, i16),
     RegisterAddressOffset(RegisterName, RegisterName),
-    RegisterAddressOffsetDisplacement(RegisterName, RegisterName, u16),
+    RegisterAddressOffsetDisplacement(RegisterName, RegisterName, i16),
     DirectAddress(u16),
 }
 
-impl std::fmt::Display for RegisterMemory {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            RegisterMemory::Register(register) => register.to_string(),
+fn format_displacement(displacement: i16) -> String {
+    match displacement {
+        0 => String::new(),
+        displacement if displacement < 0 => format!(" - {}", displacement.unsigned_abs()),
+        displacement => format!(" + {}", displacement),
+    }
+}
+
+// NASM shortens a jmp whose target is within reach of a short jump, so an E9
+// jump that could have been EB has to say `near` to assemble back the same.
+fn near_modifier(mnemonic: &Mnemonic, increment: i16) -> &'static str {
+    if *mnemonic == Mnemonic::JMP && i8::try_from(i32::from(increment) + 1).is_ok() {
+        "near "
+    } else {
+        ""
+    }
+}
+
+impl RegisterMemory {
+    fn format(&self, segment_override: Option<&RegisterName>) -> String {
+        let address = match self {
+            RegisterMemory::Register(register) => return register.to_string(),
             RegisterMemory::RegisterAddress(register) => format!("[{}]", register),
             RegisterMemory::RegisterAddressDisplacement(register, displacement) => {
-                if *displacement == 0 {
-                    format!("[{}]", register)
-                } else {
-                    format!("[{} + {}]", register, displacement)
-                }
+                format!("[{}{}]", register, format_displacement(*displacement))
             }
             RegisterMemory::RegisterAddressOffset(register, offset_register) => {
                 format!("[{} + {}]", register, offset_register)
@@ -140,32 +310,65 @@ impl std::fmt::Display for RegisterMemory {
                 offset_register,
                 displacement,
             ) => {
-                if *displacement == 0 {
-                    format!("[{} + {}]", register, offset_register)
-                } else {
-                    format!("[{} + {} + {}]", register, offset_register, displacement)
-                }
+                format!(
+                    "[{} + {}{}]",
+                    register,
+                    offset_register,
+                    format_displacement(*displacement)
+                )
             }
             RegisterMemory::DirectAddress(address) => format!("[{}]", address),
         };
-        write!(f, "{}", s)
+        match segment_override {
+            Some(segment) => format!("{}:{}", segment, address),
+            None => address,
+        }
+    }
+
+    fn is_memory(&self) -> bool {
+        !matches!(self, RegisterMemory::Register(_))
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum InstructionCategory {
+impl std::fmt::Display for RegisterMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(None))
+    }
+}
+
+/// The operation and operands of a decoded instruction, grouped by the shape
+/// of its encoding.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum InstructionCategory {
     RegisterMemoryAndRegister(Mnemonic, RegisterMemory, RegisterMemory),
     ImmediateToRegister(Mnemonic, u16, RegisterName),
     ImmediateToRegisterMemory(Mnemonic, u16, RegisterMemory, bool),
     ImmediateToAccumulator(Mnemonic, u16, RegisterName),
+    SingleOperand(Mnemonic, RegisterMemory, bool),
+    Shift(Mnemonic, RegisterMemory, bool, bool),
+    NoOperands(Mnemonic),
+    Immediate(Mnemonic, u16),
+    StringManipulation(Mnemonic, bool),
+    FixedPort(Mnemonic, u8, RegisterName),
+    VariablePort(Mnemonic, RegisterName),
     Jump(Mnemonic, i8),
+    NearJump(Mnemonic, i16),
+    FarJump(Mnemonic, u16, u16),
+    IndirectFar(Mnemonic, RegisterMemory),
+    Escape(u8, RegisterMemory),
 }
 
-impl std::fmt::Display for InstructionCategory {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
+impl InstructionCategory {
+    fn format(&self, segment_override: Option<&RegisterName>) -> String {
+        match self {
             InstructionCategory::RegisterMemoryAndRegister(mnemonic, src, dest) => {
-                format!("{} {}, {}", mnemonic, dest, src)
+                format!(
+                    "{} {}, {}",
+                    mnemonic,
+                    dest.format(segment_override),
+                    src.format(segment_override)
+                )
             }
             InstructionCategory::ImmediateToRegister(mnemonic, immediate, register) => {
                 format!("{} {}, {}", mnemonic, register, immediate)
@@ -188,83 +391,530 @@ impl std::fmt::Display for InstructionCategory {
                     "{} {} {}, {}",
                     mnemonic,
                     if *word_operation { "word" } else { "byte" },
-                    dest,
+                    dest.format(segment_override),
                     immediate
                 )
             }
             InstructionCategory::ImmediateToAccumulator(mnemonic, immediate, dest) => {
                 format!("{} {}, {}", mnemonic, dest, immediate)
             }
-            InstructionCategory::Jump(mnemonic, increment) => format!("{} {}", mnemonic, increment),
-        };
-        write!(f, "{}", s)
+            InstructionCategory::SingleOperand(mnemonic, RegisterMemory::Register(register), _) => {
+                format!("{} {}", mnemonic, register)
+            }
+            InstructionCategory::SingleOperand(mnemonic, operand, word_operation) => {
+                format!(
+                    "{} {} {}",
+                    mnemonic,
+                    if *word_operation { "word" } else { "byte" },
+                    operand.format(segment_override)
+                )
+            }
+            InstructionCategory::Shift(mnemonic, operand, word_operation, by_cl) => {
+                let count = if *by_cl { "cl" } else { "1" };
+                match operand {
+                    RegisterMemory::Register(register) => {
+                        format!("{} {}, {}", mnemonic, register, count)
+                    }
+                    _ => format!(
+                        "{} {} {}, {}",
+                        mnemonic,
+                        if *word_operation { "word" } else { "byte" },
+                        operand.format(segment_override),
+                        count
+                    ),
+                }
+            }
+            InstructionCategory::NoOperands(mnemonic) => mnemonic.to_string(),
+            InstructionCategory::Immediate(mnemonic, immediate) => {
+                format!("{} {}", mnemonic, immediate)
+            }
+            InstructionCategory::StringManipulation(mnemonic, word_operation) => {
+                format!("{}{}", mnemonic, if *word_operation { "w" } else { "b" })
+            }
+            InstructionCategory::FixedPort(Mnemonic::OUT, port, register) => {
+                format!("{} {}, {}", Mnemonic::OUT, port, register)
+            }
+            InstructionCategory::FixedPort(mnemonic, port, register) => {
+                format!("{} {}, {}", mnemonic, register, port)
+            }
+            InstructionCategory::VariablePort(Mnemonic::OUT, register) => {
+                format!("{} dx, {}", Mnemonic::OUT, register)
+            }
+            InstructionCategory::VariablePort(mnemonic, register) => {
+                format!("{} {}, dx", mnemonic, register)
+            }
+            InstructionCategory::Jump(mnemonic, increment) => {
+                format!("{} ${:+}", mnemonic, i32::from(*increment) + 2)
+            }
+            InstructionCategory::NearJump(mnemonic, increment) => format!(
+                "{} {}${:+}",
+                mnemonic,
+                near_modifier(mnemonic, *increment),
+                i32::from(*increment) + 3
+            ),
+            InstructionCategory::FarJump(mnemonic, segment, offset) => {
+                format!("{} {}:{}", mnemonic, segment, offset)
+            }
+            InstructionCategory::IndirectFar(mnemonic, operand) => {
+                format!("{} far {}", mnemonic, operand.format(segment_override))
+            }
+            InstructionCategory::Escape(opcode, operand) => {
+                format!("esc {}, {}", opcode, operand.format(segment_override))
+            }
+        }
     }
+
+    fn has_memory_operand(&self) -> bool {
+        match self {
+            InstructionCategory::RegisterMemoryAndRegister(_, src, dest) => {
+                src.is_memory() || dest.is_memory()
+            }
+            InstructionCategory::ImmediateToRegisterMemory(_, _, operand, _)
+            | InstructionCategory::SingleOperand(_, operand, _)
+            | InstructionCategory::Shift(_, operand, _, _)
+            | InstructionCategory::IndirectFar(_, operand)
+            | InstructionCategory::Escape(_, operand) => operand.is_memory(),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for InstructionCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(None))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Repeat {
+    Rep,
+    Repne,
 }
 
+/// One decoded instruction, including its prefixes.
+#[derive(Debug, Clone)]
 pub struct Instruction {
     pub(crate) length: u8,
     pub(crate) instruction_category: InstructionCategory,
+    pub(crate) lock: bool,
+    pub(crate) repeat: Option<Repeat>,
+    pub(crate) segment_override: Option<RegisterName>,
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(None))
+    }
 }
 
 impl Instruction {
-    pub fn clocks(&self) -> (u8, u8) {
-        match self.instruction_category {
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::MOV,
-                RegisterMemory::Register(_),
-                RegisterMemory::Register(_),
-            ) => (2, 0),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::MOV,
-                RegisterMemory::DirectAddress(_),
-                RegisterMemory::Register(_),
-            ) => (8, 6),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::MOV,
-                RegisterMemory::RegisterAddress(_),
-                RegisterMemory::Register(_),
-            ) => (8, 5),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::MOV,
-                RegisterMemory::RegisterAddressDisplacement(_, displacement),
-                RegisterMemory::Register(_),
-            ) => (8, if displacement > 0 { 9 } else { 5 }),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::MOV,
-                RegisterMemory::Register(_),
-                RegisterMemory::RegisterAddress(_),
-            ) => (9, 5),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::MOV,
-                RegisterMemory::Register(_),
-                RegisterMemory::RegisterAddressDisplacement(_, displacement),
-            ) => (9, if displacement > 0 { 9 } else { 5 }),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::ADD,
-                RegisterMemory::Register(_),
-                RegisterMemory::Register(_),
-            ) => (3, 0),
-            InstructionCategory::RegisterMemoryAndRegister(
-                Mnemonic::ADD,
-                RegisterMemory::Register(_),
-                RegisterMemory::RegisterAddressDisplacement(_, displacement),
-            ) => (16, if displacement > 0 { 9 } else { 5 }),
-            InstructionCategory::RegisterMemoryAndRegister(_, _, _) => todo!(),
-            InstructionCategory::ImmediateToRegister(Mnemonic::MOV, _, _) => (4, 0),
-            InstructionCategory::ImmediateToRegister(_, _, _) => todo!(),
-            InstructionCategory::ImmediateToRegisterMemory(
-                Mnemonic::ADD,
+    fn new(length: u8, instruction_category: InstructionCategory) -> Instruction {
+        Instruction {
+            length,
+            instruction_category,
+            lock: false,
+            repeat: None,
+            segment_override: None,
+        }
+    }
+
+    /// The length in bytes, prefixes included.
+    pub fn length(&self) -> u8 {
+        self.length
+    }
+
+    pub fn category(&self) -> &InstructionCategory {
+        &self.instruction_category
+    }
+
+    pub fn lock(&self) -> bool {
+        self.lock
+    }
+
+    pub fn repeat(&self) -> Option<Repeat> {
+        self.repeat
+    }
+
+    pub fn segment_override(&self) -> Option<RegisterName> {
+        self.segment_override
+    }
+
+    /// Formats the instruction as NASM source, naming the target of a relative
+    /// jump `jump_label` instead of printing its displacement.
+    pub fn format(&self, jump_label: Option<&str>) -> String {
+        let mut text = String::new();
+        if self.lock {
+            text.push_str("lock ");
+        }
+        match (&self.repeat, &self.instruction_category) {
+            (
+                Some(Repeat::Rep),
+                InstructionCategory::StringManipulation(Mnemonic::CMPS | Mnemonic::SCAS, _),
+            ) => text.push_str("repe "),
+            (Some(Repeat::Rep), _) => text.push_str("rep "),
+            (Some(Repeat::Repne), _) => text.push_str("repne "),
+            (None, _) => {}
+        }
+        let operation = match (jump_label, &self.instruction_category) {
+            (Some(label), InstructionCategory::Jump(mnemonic, _)) => {
+                format!("{} {}", mnemonic, label)
+            }
+            (Some(label), InstructionCategory::NearJump(mnemonic, increment)) => {
+                format!(
+                    "{} {}{}",
+                    mnemonic,
+                    near_modifier(mnemonic, *increment),
+                    label
+                )
+            }
+            _ => match &self.segment_override {
+                Some(segment) if self.instruction_category.has_memory_operand() => {
+                    self.instruction_category.format(Some(segment))
+                }
+                _ => self.instruction_category.format(None),
+            },
+        };
+        match &self.segment_override {
+            Some(segment) if !self.instruction_category.has_memory_operand() => {
+                text.push_str(&format!("{} {}", segment, operation))
+            }
+            _ => text.push_str(&operation),
+        }
+        text
+    }
+
+    /// Where a relative jump located at `offset` lands.
+    pub fn jump_target(&self, offset: usize) -> Option<usize> {
+        let increment = match self.instruction_category {
+            InstructionCategory::Jump(_, increment) => i16::from(increment),
+            InstructionCategory::NearJump(_, increment) => increment,
+            _ => return None,
+        };
+        let target = (offset as u16)
+            .wrapping_add(u16::from(self.length))
+            .wrapping_add(increment as u16);
+        Some(usize::from(target))
+    }
+
+    fn operation_length(&self) -> u8 {
+        self.length
+            - u8::from(self.lock)
+            - u8::from(self.repeat.is_some())
+            - u8::from(self.segment_override.is_some())
+    }
+
+    pub(crate) fn memory_operand(&self) -> Option<(&RegisterMemory, bool)> {
+        let (operand, word_operation) = match &self.instruction_category {
+            InstructionCategory::RegisterMemoryAndRegister(_, src, dest) => match (src, dest) {
+                (operand, RegisterMemory::Register(register))
+                | (RegisterMemory::Register(register), operand) => (operand, register.is_word()),
+                _ => return None,
+            },
+            InstructionCategory::ImmediateToRegisterMemory(_, _, operand, word_operation)
+            | InstructionCategory::SingleOperand(_, operand, word_operation)
+            | InstructionCategory::Shift(_, operand, word_operation, _) => {
+                (operand, *word_operation)
+            }
+            InstructionCategory::IndirectFar(_, operand)
+            | InstructionCategory::Escape(_, operand) => (operand, true),
+            _ => return None,
+        };
+        if operand.is_memory() {
+            Some((operand, word_operation))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn memory_transfers(&self) -> u16 {
+        if self.memory_operand().is_none() {
+            return 0;
+        }
+        match &self.instruction_category {
+            InstructionCategory::RegisterMemoryAndRegister(mnemonic, _, dest) => match mnemonic {
+                Mnemonic::MOV | Mnemonic::CMP | Mnemonic::TEST => 1,
+                Mnemonic::LEA => 0,
+                Mnemonic::XCHG | Mnemonic::LDS | Mnemonic::LES => 2,
+                _ if dest.is_memory() => 2,
+                _ => 1,
+            },
+            InstructionCategory::ImmediateToRegisterMemory(mnemonic, _, _, _) => match mnemonic {
+                Mnemonic::MOV | Mnemonic::CMP | Mnemonic::TEST => 1,
+                _ => 2,
+            },
+            InstructionCategory::SingleOperand(
+                Mnemonic::INC | Mnemonic::DEC | Mnemonic::NEG | Mnemonic::NOT,
+                _,
+                _,
+            ) => 2,
+            InstructionCategory::Shift(_, _, _, _) | InstructionCategory::IndirectFar(_, _) => 2,
+            _ => 1,
+        }
+    }
+
+    // Word pushes and pops the instruction makes besides any transfers
+    // through its ModRM operand. INTO only makes them when it interrupts.
+    pub(crate) fn stack_transfers(&self) -> u16 {
+        match &self.instruction_category {
+            InstructionCategory::SingleOperand(
+                Mnemonic::PUSH | Mnemonic::POP | Mnemonic::CALL,
                 _,
-                RegisterMemory::Register(_),
                 _,
-            ) => (4, 0),
-            InstructionCategory::ImmediateToRegisterMemory(_, _, _, _) => todo!(),
-            InstructionCategory::ImmediateToAccumulator(_, _, _) => todo!(),
-            InstructionCategory::Jump(_, _) => todo!(),
+            )
+            | InstructionCategory::NearJump(Mnemonic::CALL, _)
+            | InstructionCategory::NoOperands(Mnemonic::RET | Mnemonic::PUSHF | Mnemonic::POPF)
+            | InstructionCategory::Immediate(Mnemonic::RET, _) => 1,
+            InstructionCategory::FarJump(Mnemonic::CALL, _, _)
+            | InstructionCategory::IndirectFar(Mnemonic::CALL, _)
+            | InstructionCategory::NoOperands(Mnemonic::RETF)
+            | InstructionCategory::Immediate(Mnemonic::RETF, _) => 2,
+            InstructionCategory::Immediate(Mnemonic::INT, _)
+            | InstructionCategory::NoOperands(Mnemonic::INT3 | Mnemonic::INTO | Mnemonic::IRET) => {
+                3
+            }
+            _ => 0,
+        }
+    }
+
+    // Reads through DS:SI and accesses through ES:DI for each repetition of a
+    // string instruction.
+    pub(crate) fn string_transfers(&self) -> (u16, u16) {
+        match &self.instruction_category {
+            InstructionCategory::StringManipulation(mnemonic, _) => match mnemonic {
+                Mnemonic::MOVS | Mnemonic::CMPS => (1, 1),
+                Mnemonic::LODS => (1, 0),
+                _ => (0, 1),
+            },
+            _ => (0, 0),
+        }
+    }
+
+    // `count` is CL for shifts and rotates by CL and the number of iterations
+    // a repeated string instruction ran. Where the manual gives a
+    // data-dependent range we use the upper bound.
+    pub fn clocks(&self, jump_taken: bool, count: u16) -> Clocks {
+        let memory = |base: u32, operand: &RegisterMemory| Clocks {
+            base,
+            effective_address: effective_address_clocks(operand)
+                + if self.segment_override.is_some() {
+                    2
+                } else {
+                    0
+                },
+        };
+        let register = |base: u32| Clocks {
+            base,
+            effective_address: 0,
+        };
+        let by_operand = |register_clocks: u32, memory_clocks: u32, operand: &RegisterMemory| {
+            if operand.is_memory() {
+                memory(memory_clocks, operand)
+            } else {
+                register(register_clocks)
+            }
+        };
+
+        match &self.instruction_category {
+            InstructionCategory::RegisterMemoryAndRegister(mnemonic, src, dest) => {
+                let (is_source, operand) = if src.is_memory() {
+                    (true, src)
+                } else {
+                    (false, dest)
+                };
+                match mnemonic {
+                    Mnemonic::MOV
+                        if self.operation_length() == 3
+                            && matches!(operand, RegisterMemory::DirectAddress(_))
+                            && matches!(
+                                (src, dest),
+                                (
+                                    _,
+                                    RegisterMemory::Register(RegisterName::AL | RegisterName::AX)
+                                ) | (
+                                    RegisterMemory::Register(RegisterName::AL | RegisterName::AX),
+                                    _
+                                )
+                            ) =>
+                    {
+                        register(10)
+                    }
+                    Mnemonic::MOV => by_operand(2, if is_source { 8 } else { 9 }, operand),
+                    Mnemonic::CMP | Mnemonic::TEST => by_operand(3, 9, operand),
+                    Mnemonic::XCHG if self.operation_length() == 1 => register(3),
+                    Mnemonic::XCHG => by_operand(4, 17, operand),
+                    Mnemonic::LEA => memory(2, operand),
+                    Mnemonic::LDS | Mnemonic::LES => memory(16, operand),
+                    _ => by_operand(3, if is_source { 9 } else { 16 }, operand),
+                }
+            }
+            InstructionCategory::ImmediateToRegister(_, _, _) => register(4),
+            InstructionCategory::ImmediateToRegisterMemory(mnemonic, _, operand, _) => {
+                match mnemonic {
+                    Mnemonic::MOV => by_operand(4, 10, operand),
+                    Mnemonic::CMP => by_operand(4, 10, operand),
+                    Mnemonic::TEST => by_operand(5, 11, operand),
+                    _ => by_operand(4, 17, operand),
+                }
+            }
+            InstructionCategory::ImmediateToAccumulator(_, _, _) => register(4),
+            InstructionCategory::SingleOperand(mnemonic, operand, word_operation) => {
+                let word_register = |register_clocks: u32, word_clocks: u32| {
+                    if *word_operation {
+                        word_clocks
+                    } else {
+                        register_clocks
+                    }
+                };
+                match mnemonic {
+                    Mnemonic::INC | Mnemonic::DEC if self.operation_length() == 1 => register(2),
+                    Mnemonic::INC | Mnemonic::DEC => by_operand(3, 15, operand),
+                    Mnemonic::NEG | Mnemonic::NOT => by_operand(3, 16, operand),
+                    Mnemonic::MUL => {
+                        by_operand(word_register(77, 133), word_register(83, 139), operand)
+                    }
+                    Mnemonic::IMUL => {
+                        by_operand(word_register(98, 154), word_register(104, 160), operand)
+                    }
+                    Mnemonic::DIV => {
+                        by_operand(word_register(90, 162), word_register(96, 168), operand)
+                    }
+                    Mnemonic::IDIV => {
+                        by_operand(word_register(112, 184), word_register(118, 190), operand)
+                    }
+                    Mnemonic::PUSH if self.operation_length() == 1 => match operand {
+                        RegisterMemory::Register(
+                            RegisterName::ES
+                            | RegisterName::CS
+                            | RegisterName::SS
+                            | RegisterName::DS,
+                        ) => register(10),
+                        _ => register(11),
+                    },
+                    Mnemonic::PUSH => by_operand(11, 16, operand),
+                    Mnemonic::POP => by_operand(8, 17, operand),
+                    Mnemonic::CALL => by_operand(16, 21, operand),
+                    Mnemonic::JMP => by_operand(11, 18, operand),
+                    _ => by_operand(3, 16, operand),
+                }
+            }
+            InstructionCategory::Shift(_, operand, _, by_cl) => {
+                if *by_cl {
+                    let mut clocks = by_operand(8, 20, operand);
+                    clocks.base += 4 * u32::from(count);
+                    clocks
+                } else {
+                    by_operand(2, 15, operand)
+                }
+            }
+            InstructionCategory::NoOperands(mnemonic) => register(match mnemonic {
+                Mnemonic::AAA | Mnemonic::AAS | Mnemonic::DAA | Mnemonic::DAS => 4,
+                Mnemonic::AAM => 83,
+                Mnemonic::AAD => 60,
+                Mnemonic::CBW => 2,
+                Mnemonic::CWD => 5,
+                Mnemonic::NOP | Mnemonic::WAIT => 3,
+                Mnemonic::LAHF | Mnemonic::SAHF => 4,
+                Mnemonic::PUSHF => 10,
+                Mnemonic::POPF => 8,
+                Mnemonic::XLAT => 11,
+                Mnemonic::RET => 8,
+                Mnemonic::RETF => 18,
+                Mnemonic::INT3 => 52,
+                Mnemonic::INTO if jump_taken => 53,
+                Mnemonic::INTO => 4,
+                Mnemonic::IRET => 24,
+                _ => 2,
+            }),
+            InstructionCategory::Immediate(mnemonic, _) => register(match mnemonic {
+                Mnemonic::INT => 51,
+                Mnemonic::AAM => 83,
+                Mnemonic::AAD => 60,
+                Mnemonic::RET => 12,
+                _ => 17,
+            }),
+            InstructionCategory::StringManipulation(mnemonic, _) => {
+                let (single, repeated) = match mnemonic {
+                    Mnemonic::MOVS => (18, 17),
+                    Mnemonic::CMPS => (22, 22),
+                    Mnemonic::SCAS => (15, 15),
+                    Mnemonic::LODS => (12, 13),
+                    _ => (11, 10),
+                };
+                match self.repeat {
+                    Some(_) => register(9 + repeated * u32::from(count)),
+                    None => register(single),
+                }
+            }
+            InstructionCategory::FixedPort(_, _, _) => register(10),
+            InstructionCategory::VariablePort(_, _) => register(8),
+            InstructionCategory::Jump(mnemonic, _) => register(match (mnemonic, jump_taken) {
+                (Mnemonic::JMP, _) => 15,
+                (Mnemonic::LOOP, true) => 17,
+                (Mnemonic::LOOP, false) => 5,
+                (Mnemonic::LOOPE | Mnemonic::JCXZ, true) => 18,
+                (Mnemonic::LOOPE | Mnemonic::JCXZ, false) => 6,
+                (Mnemonic::LOOPNE, true) => 19,
+                (Mnemonic::LOOPNE, false) => 5,
+                (_, true) => 16,
+                (_, false) => 4,
+            }),
+            InstructionCategory::NearJump(Mnemonic::CALL, _) => register(19),
+            InstructionCategory::NearJump(_, _) => register(15),
+            InstructionCategory::FarJump(Mnemonic::CALL, _, _) => register(28),
+            InstructionCategory::FarJump(_, _, _) => register(15),
+            InstructionCategory::IndirectFar(Mnemonic::CALL, operand) => memory(37, operand),
+            InstructionCategory::IndirectFar(_, operand) => memory(24, operand),
+            InstructionCategory::Escape(_, operand) => by_operand(2, 8, operand),
+        }
+    }
+}
+
+fn effective_address_clocks(register_memory: &RegisterMemory) -> u16 {
+    match register_memory {
+        RegisterMemory::Register(_) => 0,
+        RegisterMemory::DirectAddress(_) => 6,
+        RegisterMemory::RegisterAddress(_) => 5,
+        RegisterMemory::RegisterAddressDisplacement(_, 0) => 5,
+        RegisterMemory::RegisterAddressDisplacement(_, _) => 9,
+        RegisterMemory::RegisterAddressOffset(register, offset_register) => {
+            match (register, offset_register) {
+                (RegisterName::BP, RegisterName::DI) | (RegisterName::BX, RegisterName::SI) => 7,
+                _ => 8,
+            }
+        }
+        RegisterMemory::RegisterAddressOffsetDisplacement(register, offset_register, 0) => {
+            effective_address_clocks(&RegisterMemory::RegisterAddressOffset(
+                *register,
+                *offset_register,
+            ))
+        }
+        RegisterMemory::RegisterAddressOffsetDisplacement(register, offset_register, _) => {
+            match (register, offset_register) {
+                (RegisterName::BP, RegisterName::DI) | (RegisterName::BX, RegisterName::SI) => 11,
+                _ => 12,
+            }
         }
     }
 }
 
+/// The clock table cost of an instruction, before any penalties for word
+/// transfers.
+#[derive(Debug, Default, PartialEq)]
+pub struct Clocks {
+    pub(crate) base: u32,
+    pub(crate) effective_address: u16,
+}
+
+impl Clocks {
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub fn effective_address(&self) -> u16 {
+        self.effective_address
+    }
+}
+
 #[derive(Debug)]
 struct RegMemoryWithRegisterToEitherOperands {
     instruction_length: u8,
@@ -280,126 +930,358 @@ struct ImmediateToRegisterMemoryOperands {
 }
 
 #[derive(Debug)]
-pub(crate) enum DecodeError {
-    InvalidInstruction,
-    InvalidMode,
-    InvalidRegister,
+struct RegisterMemoryOperand {
+    instruction_length: u8,
+    reg_field: u8,
+    register_memory: RegisterMemory,
+}
+
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DecodeError {
+    Truncated {
+        needed: usize,
+        available: usize,
+    },
+    UnknownOpcode {
+        opcode: u8,
+        offset: usize,
+    },
+    InvalidModRm {
+        opcode: u8,
+        modrm: u8,
+        offset: usize,
+    },
+    TooLong {
+        offset: usize,
+    },
 }
 
-pub(crate) fn decode_instruction(remaining_bytes: &[u8]) -> Result<Instruction, DecodeError> {
-    let instruction = remaining_bytes[0];
+impl DecodeError {
+    fn offset_by(self, prefix_length: usize) -> DecodeError {
+        match self {
+            DecodeError::Truncated { needed, available } => DecodeError::Truncated {
+                needed: needed + prefix_length,
+                available: available + prefix_length,
+            },
+            DecodeError::UnknownOpcode { opcode, offset } => DecodeError::UnknownOpcode {
+                opcode,
+                offset: offset + prefix_length,
+            },
+            DecodeError::InvalidModRm {
+                opcode,
+                modrm,
+                offset,
+            } => DecodeError::InvalidModRm {
+                opcode,
+                modrm,
+                offset: offset + prefix_length,
+            },
+            DecodeError::TooLong { offset } => DecodeError::TooLong {
+                offset: offset + prefix_length,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated { needed, available } => write!(
+                f,
+                "truncated instruction: needed {} bytes but only {} available",
+                needed, available
+            ),
+            DecodeError::UnknownOpcode { opcode, offset } => {
+                write!(f, "unknown opcode {:#04x} at offset {}", opcode, offset)
+            }
+            DecodeError::InvalidModRm {
+                opcode,
+                modrm,
+                offset,
+            } => write!(
+                f,
+                "invalid ModRM byte {:#04x} for opcode {:#04x} at offset {}",
+                modrm, opcode, offset
+            ),
+            DecodeError::TooLong { offset } => {
+                write!(f, "instruction at offset {} is too long", offset)
+            }
+        }
+    }
+}
+
+/// Decodes the instruction at the start of `remaining_bytes`. Bytes past the
+/// instruction's length are ignored.
+pub fn decode_instruction(remaining_bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let mut lock = false;
+    let mut repeat = None;
+    let mut segment_override = None;
+
+    let mut prefix_length = 0;
+    loop {
+        match byte_at(remaining_bytes, prefix_length)? {
+            0b11110000 => lock = true,
+            0b11110010 => repeat = Some(Repeat::Repne),
+            0b11110011 => repeat = Some(Repeat::Rep),
+            0b00100110 => segment_override = Some(RegisterName::ES),
+            0b00101110 => segment_override = Some(RegisterName::CS),
+            0b00110110 => segment_override = Some(RegisterName::SS),
+            0b00111110 => segment_override = Some(RegisterName::DS),
+            _ => break,
+        }
+        prefix_length += 1;
+    }
+
+    let mut instruction = decode_operation(&remaining_bytes[prefix_length..])
+        .map_err(|error| error.offset_by(prefix_length))?;
+    instruction.length = u8::try_from(usize::from(instruction.length) + prefix_length)
+        .map_err(|_| DecodeError::TooLong { offset: 0 })?;
+    instruction.lock = lock;
+    instruction.repeat = repeat;
+    instruction.segment_override = segment_override;
+    Ok(instruction)
+}
+
+fn decode_operation(remaining_bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let instruction = byte_at(remaining_bytes, 0)?;
 
     if (instruction & 0b11111100) == 0b10000000 {
-        let mnemonic = match (remaining_bytes[1] & 0b00111000) >> 3 {
-            0x0 => Mnemonic::ADD,
-            0x5 => Mnemonic::SUB,
-            0x7 => Mnemonic::CMP,
-            _ => return Err(DecodeError::InvalidInstruction),
-        };
-        let word_operation = (remaining_bytes[0] & 0x1) != 0;
-        let sign_extension = (remaining_bytes[0] & 0x2) != 0;
-        return Ok(decode_immediate_to_register_memory(
+        let mnemonic = decode_arithmetic_mnemonic(byte_at(remaining_bytes, 1)? >> 3);
+        let word_operation = (instruction & 0x1) != 0;
+        let sign_extension = (instruction & 0x2) != 0;
+        return decode_immediate_to_register_memory(
             mnemonic,
             word_operation,
             sign_extension,
             remaining_bytes,
-        ));
+        );
     }
 
-    if (instruction & 0b11111100) == 0b10001000 {
-        return Ok(decode_reg_memory_and_register_to_either(
-            Mnemonic::MOV,
+    // ADD, OR, ADC, SBB, AND, SUB, XOR and CMP share their encodings, with
+    // the operation selected by bits 3-5 of the opcode.
+    if (instruction & 0b11000100) == 0b00000000 {
+        return decode_reg_memory_and_register_to_either(
+            decode_arithmetic_mnemonic(instruction >> 3),
             remaining_bytes,
-        ));
+        );
+    }
+    if (instruction & 0b11000110) == 0b00000100 {
+        return decode_immediate_to_accumulator(
+            decode_arithmetic_mnemonic(instruction >> 3),
+            remaining_bytes,
+        );
+    }
+
+    if (instruction & 0b11111100) == 0b10001000 {
+        return decode_reg_memory_and_register_to_either(Mnemonic::MOV, remaining_bytes);
     }
     if (instruction & 0b11111110) == 0b11000110 {
-        let word_operation = (remaining_bytes[0] & 0x1) != 0;
-        return Ok(decode_immediate_to_register_memory(
+        let operands_byte = byte_at(remaining_bytes, 1)?;
+        if (operands_byte & 0b00111000) != 0 {
+            return Err(DecodeError::InvalidModRm {
+                opcode: instruction,
+                modrm: operands_byte,
+                offset: 0,
+            });
+        }
+        let word_operation = (instruction & 0x1) != 0;
+        return decode_immediate_to_register_memory(
             Mnemonic::MOV,
             word_operation,
             false,
             remaining_bytes,
-        ));
+        );
     }
     if (instruction & 0b11110000) == 0b10110000 {
-        return Ok(decode_immediate_to_register(Mnemonic::MOV, remaining_bytes));
+        return decode_immediate_to_register(Mnemonic::MOV, remaining_bytes);
+    }
+    if (instruction & 0b11111100) == 0b10100000 {
+        return decode_memory_and_accumulator(remaining_bytes);
     }
 
-    if (instruction & 0b11111100) == 0b00000000 {
-        return Ok(decode_reg_memory_and_register_to_either(
-            Mnemonic::ADD,
-            remaining_bytes,
-        ));
+    if (instruction & 0b11111110) == 0b10000100 {
+        return decode_reg_memory_and_register_to_either(Mnemonic::TEST, remaining_bytes);
     }
-    if (instruction & 0b11111110) == 0b00000100 {
-        return Ok(decode_immediate_to_accumulator(
-            Mnemonic::ADD,
-            remaining_bytes,
-        ));
+    if (instruction & 0b11111110) == 0b10101000 {
+        return decode_immediate_to_accumulator(Mnemonic::TEST, remaining_bytes);
+    }
+    if (instruction & 0b11111110) == 0b10000110 {
+        return decode_reg_memory_and_register_to_either(Mnemonic::XCHG, remaining_bytes);
     }
 
-    if (instruction & 0b11111100) == 0b00101000 {
-        return Ok(decode_reg_memory_and_register_to_either(
-            Mnemonic::SUB,
-            remaining_bytes,
-        ));
+    if (instruction & 0b11111000) == 0b01000000 {
+        return Ok(decode_register(Mnemonic::INC, instruction));
     }
-    if (instruction & 0b11111110) == 0b00101100 {
-        return Ok(decode_immediate_to_accumulator(
-            Mnemonic::SUB,
-            remaining_bytes,
+    if (instruction & 0b11111000) == 0b01001000 {
+        return Ok(decode_register(Mnemonic::DEC, instruction));
+    }
+    if (instruction & 0b11111000) == 0b01010000 {
+        return Ok(decode_register(Mnemonic::PUSH, instruction));
+    }
+    if (instruction & 0b11111000) == 0b01011000 {
+        return Ok(decode_register(Mnemonic::POP, instruction));
+    }
+    if (instruction & 0b11111000) == 0b10010000 && instruction != 0b10010000 {
+        let register = decode_register_name(instruction & 0x7, true);
+        return Ok(Instruction::new(
+            1,
+            InstructionCategory::RegisterMemoryAndRegister(
+                Mnemonic::XCHG,
+                RegisterMemory::Register(RegisterName::AX),
+                RegisterMemory::Register(register),
+            ),
         ));
     }
+    if (instruction & 0b11100111) == 0b00000110 {
+        return Ok(decode_segment_register(Mnemonic::PUSH, instruction));
+    }
+    if (instruction & 0b11100111) == 0b00000111 && instruction != 0b00001111 {
+        return Ok(decode_segment_register(Mnemonic::POP, instruction));
+    }
 
-    if (instruction & 0b11111100) == 0b00111000 {
-        return Ok(decode_reg_memory_and_register_to_either(
-            Mnemonic::CMP,
-            remaining_bytes,
-        ));
+    if (instruction & 0b11111100) == 0b11010000 {
+        return decode_shift(remaining_bytes);
     }
-    if (instruction & 0b11111110) == 0b00111100 {
-        return Ok(decode_immediate_to_accumulator(
-            Mnemonic::CMP,
-            remaining_bytes,
-        ));
+    if (instruction & 0b11111000) == 0b11011000 {
+        return decode_escape(remaining_bytes);
+    }
+    if (instruction & 0b11111110) == 0b11110110 {
+        return decode_group_3(remaining_bytes);
+    }
+
+    if (instruction & 0b11110000) == 0b01110000 {
+        let mnemonic = match instruction & 0x0F {
+            0x0 => Mnemonic::JO,
+            0x1 => Mnemonic::JNO,
+            0x2 => Mnemonic::JC,
+            0x3 => Mnemonic::JNC,
+            0x4 => Mnemonic::JZ,
+            0x5 => Mnemonic::JNZ,
+            0x6 => Mnemonic::JNA,
+            0x7 => Mnemonic::JA,
+            0x8 => Mnemonic::JS,
+            0x9 => Mnemonic::JNS,
+            0xA => Mnemonic::JPE,
+            0xB => Mnemonic::JPO,
+            0xC => Mnemonic::JL,
+            0xD => Mnemonic::JNL,
+            0xE => Mnemonic::JNG,
+            _ => Mnemonic::JG,
+        };
+        return decode_jump(mnemonic, remaining_bytes);
     }
 
     match instruction {
-        0b01110100 => return Ok(decode_jump(Mnemonic::JZ, remaining_bytes)),
-        0b01111100 => return Ok(decode_jump(Mnemonic::JL, remaining_bytes)),
-        0b01111110 => return Ok(decode_jump(Mnemonic::JNG, remaining_bytes)),
-        0b01110010 => return Ok(decode_jump(Mnemonic::JC, remaining_bytes)),
-        0b01110110 => return Ok(decode_jump(Mnemonic::JNA, remaining_bytes)),
-        0b01111010 => return Ok(decode_jump(Mnemonic::JPE, remaining_bytes)),
-        0b01110000 => return Ok(decode_jump(Mnemonic::JO, remaining_bytes)),
-        0b01111000 => return Ok(decode_jump(Mnemonic::JS, remaining_bytes)),
-        0b01110101 => return Ok(decode_jump(Mnemonic::JNZ, remaining_bytes)),
-        0b01111101 => return Ok(decode_jump(Mnemonic::JNL, remaining_bytes)),
-        0b01111111 => return Ok(decode_jump(Mnemonic::JG, remaining_bytes)),
-        0b01110011 => return Ok(decode_jump(Mnemonic::JNC, remaining_bytes)),
-        0b01110111 => return Ok(decode_jump(Mnemonic::JA, remaining_bytes)),
-        0b01111011 => return Ok(decode_jump(Mnemonic::JPO, remaining_bytes)),
-        0b01110001 => return Ok(decode_jump(Mnemonic::JNO, remaining_bytes)),
-        0b01111001 => return Ok(decode_jump(Mnemonic::JNS, remaining_bytes)),
-        0b11100010 => return Ok(decode_jump(Mnemonic::LOOP, remaining_bytes)),
-        0b11100001 => return Ok(decode_jump(Mnemonic::LOOPE, remaining_bytes)),
-        0b11100000 => return Ok(decode_jump(Mnemonic::LOOPNE, remaining_bytes)),
-        0b11100011 => return Ok(decode_jump(Mnemonic::JCXZ, remaining_bytes)),
-        _ => Err(DecodeError::InvalidInstruction),
+        0b11100010 => decode_jump(Mnemonic::LOOP, remaining_bytes),
+        0b11100001 => decode_jump(Mnemonic::LOOPE, remaining_bytes),
+        0b11100000 => decode_jump(Mnemonic::LOOPNE, remaining_bytes),
+        0b11100011 => decode_jump(Mnemonic::JCXZ, remaining_bytes),
+        0b11101011 => decode_jump(Mnemonic::JMP, remaining_bytes),
+        0b11101000 => decode_near_jump(Mnemonic::CALL, remaining_bytes),
+        0b11101001 => decode_near_jump(Mnemonic::JMP, remaining_bytes),
+        0b10011010 => decode_far_jump(Mnemonic::CALL, remaining_bytes),
+        0b11101010 => decode_far_jump(Mnemonic::JMP, remaining_bytes),
+
+        0b10001100 | 0b10001110 => decode_segment_register_and_register_memory(remaining_bytes),
+        0b10001101 => decode_load_address(Mnemonic::LEA, remaining_bytes),
+        0b11000101 => decode_load_address(Mnemonic::LDS, remaining_bytes),
+        0b11000100 => decode_load_address(Mnemonic::LES, remaining_bytes),
+        0b10001111 => decode_pop_register_memory(remaining_bytes),
+        0b11111110 => decode_group_4(remaining_bytes),
+        0b11111111 => decode_group_5(remaining_bytes),
+
+        0b11100100 | 0b11100101 => decode_fixed_port(Mnemonic::IN, remaining_bytes),
+        0b11100110 | 0b11100111 => decode_fixed_port(Mnemonic::OUT, remaining_bytes),
+        0b11101100 | 0b11101101 => Ok(decode_variable_port(Mnemonic::IN, instruction)),
+        0b11101110 | 0b11101111 => Ok(decode_variable_port(Mnemonic::OUT, instruction)),
+
+        0b10100100 | 0b10100101 => Ok(decode_string(Mnemonic::MOVS, instruction)),
+        0b10100110 | 0b10100111 => Ok(decode_string(Mnemonic::CMPS, instruction)),
+        0b10101010 | 0b10101011 => Ok(decode_string(Mnemonic::STOS, instruction)),
+        0b10101100 | 0b10101101 => Ok(decode_string(Mnemonic::LODS, instruction)),
+        0b10101110 | 0b10101111 => Ok(decode_string(Mnemonic::SCAS, instruction)),
+
+        0b11000010 => decode_immediate(Mnemonic::RET, remaining_bytes),
+        0b11001010 => decode_immediate(Mnemonic::RETF, remaining_bytes),
+        0b11001101 => decode_immediate(Mnemonic::INT, remaining_bytes),
+        0b11010100 => decode_ascii_adjust(Mnemonic::AAM, remaining_bytes),
+        0b11010101 => decode_ascii_adjust(Mnemonic::AAD, remaining_bytes),
+
+        0b00100111 => Ok(decode_no_operands(Mnemonic::DAA)),
+        0b00101111 => Ok(decode_no_operands(Mnemonic::DAS)),
+        0b00110111 => Ok(decode_no_operands(Mnemonic::AAA)),
+        0b00111111 => Ok(decode_no_operands(Mnemonic::AAS)),
+        0b10010000 => Ok(decode_no_operands(Mnemonic::NOP)),
+        0b10011000 => Ok(decode_no_operands(Mnemonic::CBW)),
+        0b10011001 => Ok(decode_no_operands(Mnemonic::CWD)),
+        0b10011011 => Ok(decode_no_operands(Mnemonic::WAIT)),
+        0b10011100 => Ok(decode_no_operands(Mnemonic::PUSHF)),
+        0b10011101 => Ok(decode_no_operands(Mnemonic::POPF)),
+        0b10011110 => Ok(decode_no_operands(Mnemonic::SAHF)),
+        0b10011111 => Ok(decode_no_operands(Mnemonic::LAHF)),
+        0b11000011 => Ok(decode_no_operands(Mnemonic::RET)),
+        0b11001011 => Ok(decode_no_operands(Mnemonic::RETF)),
+        0b11001100 => Ok(decode_no_operands(Mnemonic::INT3)),
+        0b11001110 => Ok(decode_no_operands(Mnemonic::INTO)),
+        0b11001111 => Ok(decode_no_operands(Mnemonic::IRET)),
+        0b11010111 => Ok(decode_no_operands(Mnemonic::XLAT)),
+        0b11110100 => Ok(decode_no_operands(Mnemonic::HLT)),
+        0b11110101 => Ok(decode_no_operands(Mnemonic::CMC)),
+        0b11111000 => Ok(decode_no_operands(Mnemonic::CLC)),
+        0b11111001 => Ok(decode_no_operands(Mnemonic::STC)),
+        0b11111010 => Ok(decode_no_operands(Mnemonic::CLI)),
+        0b11111011 => Ok(decode_no_operands(Mnemonic::STI)),
+        0b11111100 => Ok(decode_no_operands(Mnemonic::CLD)),
+        0b11111101 => Ok(decode_no_operands(Mnemonic::STD)),
+
+        _ => Err(DecodeError::UnknownOpcode {
+            opcode: instruction,
+            offset: 0,
+        }),
     }
 }
 
-fn decode_reg_memory_with_register_to_either_operands(
+fn byte_at(instruction_stream: &[u8], index: usize) -> Result<u8, DecodeError> {
+    instruction_stream
+        .get(index)
+        .copied()
+        .ok_or(DecodeError::Truncated {
+            needed: index + 1,
+            available: instruction_stream.len(),
+        })
+}
+
+fn word_at(instruction_stream: &[u8], index: usize) -> Result<u16, DecodeError> {
+    let high = byte_at(instruction_stream, index + 1)?;
+    let low = byte_at(instruction_stream, index)?;
+    Ok(u16::from_le_bytes([low, high]))
+}
+
+fn decode_arithmetic_mnemonic(operation: u8) -> Mnemonic {
+    match operation & 0x7 {
+        0x0 => Mnemonic::ADD,
+        0x1 => Mnemonic::OR,
+        0x2 => Mnemonic::ADC,
+        0x3 => Mnemonic::SBB,
+        0x4 => Mnemonic::AND,
+        0x5 => Mnemonic::SUB,
+        0x6 => Mnemonic::XOR,
+        _ => Mnemonic::CMP,
+    }
+}
+
+fn decode_register_memory_operand(
     instruction_stream: &[u8],
     word_operation: bool,
-) -> Result<RegMemoryWithRegisterToEitherOperands, DecodeError> {
-    let operands_byte = instruction_stream[1];
+) -> Result<RegisterMemoryOperand, DecodeError> {
+    let operands_byte = byte_at(instruction_stream, 1)?;
 
-    let register = decode_register((operands_byte & 0x38) >> 3, word_operation)?;
+    let reg_field = (operands_byte & 0x38) >> 3;
 
     let register_memory_byte = operands_byte & 0x7;
 
-    let mode = decode_mod(operands_byte >> 6)?;
+    let mode = decode_mod(operands_byte >> 6);
 
     let instruction_length: u8;
     let mut displacement: u16 = 0;
@@ -411,126 +1293,80 @@ fn decode_reg_memory_with_register_to_either_operands(
                 // DirectAddress, but we also need to check the register/memory
                 // type is a direct address to know to calculate the
                 // displacement.
-                displacement = u16::from_le_bytes([instruction_stream[2], instruction_stream[3]]);
-                if word_operation {
-                    instruction_length = 4;
-                } else {
-                    instruction_length = 3;
-                }
+                displacement = word_at(instruction_stream, 2)?;
+                instruction_length = 4;
             } else {
                 instruction_length = 2;
             }
         }
         Mode::MemoryMode8BitDisplacement => {
             instruction_length = 3;
-            displacement = instruction_stream[2] as u16;
+            displacement = byte_at(instruction_stream, 2)? as i8 as u16;
         }
         Mode::MemoryMode16BitDisplacement => {
             instruction_length = 4;
-            displacement = u16::from_le_bytes([instruction_stream[2], instruction_stream[3]]);
+            displacement = word_at(instruction_stream, 2)?;
         }
     }
 
     let register_memory =
-        decode_register_memory(register_memory_byte, &mode, displacement, word_operation)?;
+        decode_register_memory(register_memory_byte, &mode, displacement, word_operation);
 
-    Ok(RegMemoryWithRegisterToEitherOperands {
+    Ok(RegisterMemoryOperand {
         instruction_length,
-        register,
+        reg_field,
         register_memory,
     })
 }
 
-fn decode_immediate_to_register_memory_operands(
+fn decode_reg_memory_with_register_to_either_operands(
     instruction_stream: &[u8],
-    sign_extension: bool,
     word_operation: bool,
-) -> Result<ImmediateToRegisterMemoryOperands, DecodeError> {
-    let operands_byte = instruction_stream[1];
-    let word_immediate = !sign_extension && word_operation;
+) -> Result<RegMemoryWithRegisterToEitherOperands, DecodeError> {
+    let operand = decode_register_memory_operand(instruction_stream, word_operation)?;
 
-    let register_memory_byte = operands_byte & 0x7;
+    let register = decode_register_name(operand.reg_field, word_operation);
 
-    let mode = decode_mod(operands_byte >> 6)?;
+    Ok(RegMemoryWithRegisterToEitherOperands {
+        instruction_length: operand.instruction_length,
+        register,
+        register_memory: operand.register_memory,
+    })
+}
 
-    let instruction_length: u8;
-    let mut displacement: u16 = 0;
-    let immediate: u16;
-    match mode {
-        Mode::RegisterMode => {
-            if word_immediate {
-                instruction_length = 4;
-                immediate = u16::from_le_bytes([instruction_stream[2], instruction_stream[3]]);
-            } else {
-                instruction_length = 3;
-                immediate = instruction_stream[2] as u16;
-            }
-        }
-        Mode::MemoryModeNoDisplacement => {
-            if register_memory_byte == 0x6 {
-                // Shitty hack because we need the displacement to construct a
-                // DirectAddress, but we also need to check the register/memory
-                // type is a direct address to know to calculate the
-                // displacement.
-                displacement = u16::from_le_bytes([instruction_stream[2], instruction_stream[3]]);
-                if word_immediate {
-                    instruction_length = 6;
-                    immediate = u16::from_le_bytes([instruction_stream[4], instruction_stream[5]]);
-                } else {
-                    instruction_length = 5;
-                    immediate = instruction_stream[4] as u16;
-                }
-            } else {
-                if word_immediate {
-                    instruction_length = 4;
-                    immediate = u16::from_le_bytes([instruction_stream[2], instruction_stream[3]]);
-                } else {
-                    instruction_length = 3;
-                    immediate = instruction_stream[2] as u16;
-                }
-            }
-        }
-        Mode::MemoryMode8BitDisplacement => {
-            displacement = instruction_stream[2] as u16;
-            if word_immediate {
-                instruction_length = 5;
-                immediate = u16::from_le_bytes([instruction_stream[3], instruction_stream[4]]);
-            } else {
-                instruction_length = 4;
-                immediate = instruction_stream[3] as u16;
-            }
-        }
-        Mode::MemoryMode16BitDisplacement => {
-            displacement = u16::from_le_bytes([instruction_stream[2], instruction_stream[3]]);
-            if word_immediate {
-                instruction_length = 6;
-                immediate = u16::from_le_bytes([instruction_stream[4], instruction_stream[5]]);
-            } else {
-                instruction_length = 5;
-                immediate = instruction_stream[4] as u16;
-            }
-        }
-    }
+fn decode_immediate_to_register_memory_operands(
+    instruction_stream: &[u8],
+    sign_extension: bool,
+    word_operation: bool,
+) -> Result<ImmediateToRegisterMemoryOperands, DecodeError> {
+    let operand = decode_register_memory_operand(instruction_stream, word_operation)?;
 
-    let register_memory =
-        decode_register_memory(register_memory_byte, &mode, displacement, word_operation)?;
+    let immediate_index = usize::from(operand.instruction_length);
+    let (immediate, immediate_length) = if word_operation && !sign_extension {
+        (word_at(instruction_stream, immediate_index)?, 2)
+    } else if word_operation {
+        (
+            byte_at(instruction_stream, immediate_index)? as i8 as u16,
+            1,
+        )
+    } else {
+        (byte_at(instruction_stream, immediate_index)? as u16, 1)
+    };
 
     Ok(ImmediateToRegisterMemoryOperands {
-        instruction_length,
-        register_memory,
+        instruction_length: operand.instruction_length + immediate_length,
+        register_memory: operand.register_memory,
         immediate,
     })
 }
 
-fn decode_mod(mod_byte: u8) -> Result<Mode, DecodeError> {
-    let mode = match mod_byte {
+fn decode_mod(mod_byte: u8) -> Mode {
+    match mod_byte & 0x3 {
         0x0 => Mode::MemoryModeNoDisplacement,
         0x1 => Mode::MemoryMode8BitDisplacement,
         0x2 => Mode::MemoryMode16BitDisplacement,
-        0x3 => Mode::RegisterMode,
-        _ => return Err(DecodeError::InvalidMode),
-    };
-    Ok(mode)
+        _ => Mode::RegisterMode,
+    }
 }
 
 fn decode_register_memory(
@@ -538,276 +1374,491 @@ fn decode_register_memory(
     mode: &Mode,
     displacement: u16,
     word_operation: bool,
-) -> Result<RegisterMemory, DecodeError> {
-    let register_memory = match (register_memory_byte, mode) {
-        (0x0, Mode::MemoryMode8BitDisplacement) | (0x0, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressOffsetDisplacement(
-                RegisterName::BX,
-                RegisterName::SI,
-                displacement,
-            )
+) -> RegisterMemory {
+    match (register_memory_byte & 0x7, mode) {
+        (_, Mode::RegisterMode) => {
+            RegisterMemory::Register(decode_register_name(register_memory_byte, word_operation))
         }
+
         (0x0, Mode::MemoryModeNoDisplacement) => {
             RegisterMemory::RegisterAddressOffset(RegisterName::BX, RegisterName::SI)
         }
-        (0x0, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::AX)
-            } else {
-                RegisterMemory::Register(RegisterName::AL)
-            }
-        }
+        (0x0, _) => RegisterMemory::RegisterAddressOffsetDisplacement(
+            RegisterName::BX,
+            RegisterName::SI,
+            displacement as i16,
+        ),
 
-        (0x1, Mode::MemoryMode8BitDisplacement) | (0x1, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressOffsetDisplacement(
-                RegisterName::BX,
-                RegisterName::DI,
-                displacement,
-            )
-        }
         (0x1, Mode::MemoryModeNoDisplacement) => {
             RegisterMemory::RegisterAddressOffset(RegisterName::BX, RegisterName::DI)
         }
-        (0x1, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::CX)
-            } else {
-                RegisterMemory::Register(RegisterName::CL)
-            }
-        }
+        (0x1, _) => RegisterMemory::RegisterAddressOffsetDisplacement(
+            RegisterName::BX,
+            RegisterName::DI,
+            displacement as i16,
+        ),
 
-        (0x2, Mode::MemoryMode8BitDisplacement) | (0x2, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressOffsetDisplacement(
-                RegisterName::BP,
-                RegisterName::SI,
-                displacement,
-            )
-        }
         (0x2, Mode::MemoryModeNoDisplacement) => {
             RegisterMemory::RegisterAddressOffset(RegisterName::BP, RegisterName::SI)
         }
-        (0x2, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::DX)
-            } else {
-                RegisterMemory::Register(RegisterName::DL)
-            }
-        }
+        (0x2, _) => RegisterMemory::RegisterAddressOffsetDisplacement(
+            RegisterName::BP,
+            RegisterName::SI,
+            displacement as i16,
+        ),
 
-        (0x3, Mode::MemoryMode8BitDisplacement) | (0x3, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressOffsetDisplacement(
-                RegisterName::BP,
-                RegisterName::DI,
-                displacement,
-            )
-        }
         (0x3, Mode::MemoryModeNoDisplacement) => {
             RegisterMemory::RegisterAddressOffset(RegisterName::BP, RegisterName::DI)
         }
-        (0x3, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::BX)
-            } else {
-                RegisterMemory::Register(RegisterName::BL)
-            }
-        }
+        (0x3, _) => RegisterMemory::RegisterAddressOffsetDisplacement(
+            RegisterName::BP,
+            RegisterName::DI,
+            displacement as i16,
+        ),
 
-        (0x4, Mode::MemoryMode8BitDisplacement) | (0x4, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressDisplacement(RegisterName::SI, displacement)
-        }
         (0x4, Mode::MemoryModeNoDisplacement) => RegisterMemory::RegisterAddress(RegisterName::SI),
-        (0x4, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::SP)
-            } else {
-                RegisterMemory::Register(RegisterName::AH)
-            }
+        (0x4, _) => {
+            RegisterMemory::RegisterAddressDisplacement(RegisterName::SI, displacement as i16)
         }
 
-        (0x5, Mode::MemoryMode8BitDisplacement) | (0x5, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressDisplacement(RegisterName::DI, displacement)
-        }
         (0x5, Mode::MemoryModeNoDisplacement) => RegisterMemory::RegisterAddress(RegisterName::DI),
-        (0x5, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::BP)
-            } else {
-                RegisterMemory::Register(RegisterName::CH)
-            }
+        (0x5, _) => {
+            RegisterMemory::RegisterAddressDisplacement(RegisterName::DI, displacement as i16)
         }
 
-        (0x6, Mode::MemoryMode8BitDisplacement) | (0x6, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressDisplacement(RegisterName::BP, displacement)
-        }
         (0x6, Mode::MemoryModeNoDisplacement) => RegisterMemory::DirectAddress(displacement),
-        (0x6, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::SI)
-            } else {
-                RegisterMemory::Register(RegisterName::DH)
-            }
+        (0x6, _) => {
+            RegisterMemory::RegisterAddressDisplacement(RegisterName::BP, displacement as i16)
         }
 
-        (0x7, Mode::MemoryMode8BitDisplacement) | (0x7, Mode::MemoryMode16BitDisplacement) => {
-            RegisterMemory::RegisterAddressDisplacement(RegisterName::BX, displacement)
-        }
-        (0x7, Mode::MemoryModeNoDisplacement) => RegisterMemory::RegisterAddress(RegisterName::BX),
-        (0x7, Mode::RegisterMode) => {
-            if word_operation {
-                RegisterMemory::Register(RegisterName::DI)
-            } else {
-                RegisterMemory::Register(RegisterName::BH)
-            }
+        (_, Mode::MemoryModeNoDisplacement) => RegisterMemory::RegisterAddress(RegisterName::BX),
+        (_, _) => {
+            RegisterMemory::RegisterAddressDisplacement(RegisterName::BX, displacement as i16)
         }
+    }
+}
 
-        (_, _) => return Err(DecodeError::InvalidInstruction),
-    };
-    Ok(register_memory)
-}
-
-fn decode_register(register_byte: u8, word_operation: bool) -> Result<RegisterName, DecodeError> {
-    match (register_byte, word_operation) {
-        (0x0, false) => Ok(RegisterName::AL),
-        (0x1, false) => Ok(RegisterName::CL),
-        (0x2, false) => Ok(RegisterName::DL),
-        (0x3, false) => Ok(RegisterName::BL),
-        (0x4, false) => Ok(RegisterName::AH),
-        (0x5, false) => Ok(RegisterName::CH),
-        (0x6, false) => Ok(RegisterName::DH),
-        (0x7, false) => Ok(RegisterName::BH),
-        (0x0, true) => Ok(RegisterName::AX),
-        (0x1, true) => Ok(RegisterName::CX),
-        (0x2, true) => Ok(RegisterName::DX),
-        (0x3, true) => Ok(RegisterName::BX),
-        (0x4, true) => Ok(RegisterName::SP),
-        (0x5, true) => Ok(RegisterName::BP),
-        (0x6, true) => Ok(RegisterName::SI),
-        (0x7, true) => Ok(RegisterName::DI),
-        _ => return Err(DecodeError::InvalidRegister),
-    }
-}
-
-fn decode_reg_memory_and_register_to_either(mnemonic: Mnemonic, bytes: &[u8]) -> Instruction {
+fn decode_register_name(register_byte: u8, word_operation: bool) -> RegisterName {
+    match (register_byte & 0x7, word_operation) {
+        (0x0, false) => RegisterName::AL,
+        (0x1, false) => RegisterName::CL,
+        (0x2, false) => RegisterName::DL,
+        (0x3, false) => RegisterName::BL,
+        (0x4, false) => RegisterName::AH,
+        (0x5, false) => RegisterName::CH,
+        (0x6, false) => RegisterName::DH,
+        (_, false) => RegisterName::BH,
+        (0x0, true) => RegisterName::AX,
+        (0x1, true) => RegisterName::CX,
+        (0x2, true) => RegisterName::DX,
+        (0x3, true) => RegisterName::BX,
+        (0x4, true) => RegisterName::SP,
+        (0x5, true) => RegisterName::BP,
+        (0x6, true) => RegisterName::SI,
+        (_, true) => RegisterName::DI,
+    }
+}
+
+fn decode_segment_register_name(register_byte: u8) -> Option<RegisterName> {
+    match register_byte {
+        0x0 => Some(RegisterName::ES),
+        0x1 => Some(RegisterName::CS),
+        0x2 => Some(RegisterName::SS),
+        0x3 => Some(RegisterName::DS),
+        _ => None,
+    }
+}
+
+fn invalid_mod_rm(bytes: &[u8]) -> DecodeError {
+    DecodeError::InvalidModRm {
+        opcode: bytes[0],
+        modrm: bytes[1],
+        offset: 0,
+    }
+}
+
+fn decode_reg_memory_and_register_to_either(
+    mnemonic: Mnemonic,
+    bytes: &[u8],
+) -> Result<Instruction, DecodeError> {
     let word_operation = (bytes[0] & 0x1) != 0;
     let reg_is_destination = (bytes[0] & 0x2) != 0;
 
-    let operands = decode_reg_memory_with_register_to_either_operands(bytes, word_operation)
-        .expect("failed to decode operands");
+    let operands = decode_reg_memory_with_register_to_either_operands(bytes, word_operation)?;
 
     if reg_is_destination {
-        Instruction {
-            length: operands.instruction_length,
-            instruction_category: InstructionCategory::RegisterMemoryAndRegister(
+        Ok(Instruction::new(
+            operands.instruction_length,
+            InstructionCategory::RegisterMemoryAndRegister(
                 mnemonic,
                 operands.register_memory,
                 RegisterMemory::Register(operands.register),
             ),
-        }
+        ))
     } else {
-        Instruction {
-            length: operands.instruction_length,
-            instruction_category: InstructionCategory::RegisterMemoryAndRegister(
+        Ok(Instruction::new(
+            operands.instruction_length,
+            InstructionCategory::RegisterMemoryAndRegister(
                 mnemonic,
                 RegisterMemory::Register(operands.register),
                 operands.register_memory,
             ),
-        }
+        ))
     }
 }
 
+fn decode_segment_register_and_register_memory(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let segment_is_destination = (bytes[0] & 0x2) != 0;
+
+    let operand = decode_register_memory_operand(bytes, true)?;
+    let segment_register =
+        decode_segment_register_name(operand.reg_field).ok_or_else(|| invalid_mod_rm(bytes))?;
+
+    if segment_is_destination {
+        Ok(Instruction::new(
+            operand.instruction_length,
+            InstructionCategory::RegisterMemoryAndRegister(
+                Mnemonic::MOV,
+                operand.register_memory,
+                RegisterMemory::Register(segment_register),
+            ),
+        ))
+    } else {
+        Ok(Instruction::new(
+            operand.instruction_length,
+            InstructionCategory::RegisterMemoryAndRegister(
+                Mnemonic::MOV,
+                RegisterMemory::Register(segment_register),
+                operand.register_memory,
+            ),
+        ))
+    }
+}
+
+fn decode_load_address(mnemonic: Mnemonic, bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let operands = decode_reg_memory_with_register_to_either_operands(bytes, true)?;
+
+    if !operands.register_memory.is_memory() {
+        return Err(invalid_mod_rm(bytes));
+    }
+
+    Ok(Instruction::new(
+        operands.instruction_length,
+        InstructionCategory::RegisterMemoryAndRegister(
+            mnemonic,
+            operands.register_memory,
+            RegisterMemory::Register(operands.register),
+        ),
+    ))
+}
+
 fn decode_immediate_to_register_memory(
     mnemonic: Mnemonic,
     word_operation: bool,
     sign_extension: bool,
     bytes: &[u8],
-) -> Instruction {
+) -> Result<Instruction, DecodeError> {
     let operands =
-        decode_immediate_to_register_memory_operands(bytes, sign_extension, word_operation)
-            .expect("failed to decode operands");
-
-    match operands.register_memory {
-        RegisterMemory::DirectAddress(_)
-        | RegisterMemory::Register(_)
-        | RegisterMemory::RegisterAddress(
-            RegisterName::BX | RegisterName::BP | RegisterName::DI | RegisterName::SI,
-        )
-        | RegisterMemory::RegisterAddressDisplacement(
-            RegisterName::BX | RegisterName::BP | RegisterName::DI | RegisterName::SI,
-            _,
-        )
-        | RegisterMemory::RegisterAddressOffset(
-            RegisterName::BX | RegisterName::BP | RegisterName::DI | RegisterName::SI,
-            _,
-        )
-        | RegisterMemory::RegisterAddressOffsetDisplacement(
-            RegisterName::BX | RegisterName::BP | RegisterName::DI | RegisterName::SI,
-            _,
-            _,
-        ) => Instruction {
-            length: operands.instruction_length,
-            instruction_category: InstructionCategory::ImmediateToRegisterMemory(
-                mnemonic,
-                operands.immediate,
-                operands.register_memory,
-                word_operation,
-            ),
-        },
-        _ => panic!("invalid 16-bit effective address"), // Should probably return an error instead of panicking
-    }
+        decode_immediate_to_register_memory_operands(bytes, sign_extension, word_operation)?;
+
+    Ok(Instruction::new(
+        operands.instruction_length,
+        InstructionCategory::ImmediateToRegisterMemory(
+            mnemonic,
+            operands.immediate,
+            operands.register_memory,
+            word_operation,
+        ),
+    ))
 }
 
-fn decode_immediate_to_register(mnemonic: Mnemonic, bytes: &[u8]) -> Instruction {
+fn decode_immediate_to_register(
+    mnemonic: Mnemonic,
+    bytes: &[u8],
+) -> Result<Instruction, DecodeError> {
     let word_operation = (bytes[0] & 0x8) != 0;
 
-    let register =
-        decode_register(bytes[0] & 7, word_operation).expect("failed to decode register");
+    let register = decode_register_name(bytes[0], word_operation);
     let immediate = if word_operation {
-        u16::from_le_bytes([bytes[1], bytes[2]])
+        word_at(bytes, 1)?
     } else {
-        bytes[1] as u16
+        byte_at(bytes, 1)? as u16
     };
 
     let length = if word_operation { 3 } else { 2 };
-    Instruction {
+    Ok(Instruction::new(
         length,
-        instruction_category: InstructionCategory::ImmediateToRegister(
-            mnemonic, immediate, register,
-        ),
-    }
+        InstructionCategory::ImmediateToRegister(mnemonic, immediate, register),
+    ))
 }
 
-fn decode_immediate_to_accumulator(mnemonic: Mnemonic, bytes: &[u8]) -> Instruction {
+fn decode_immediate_to_accumulator(
+    mnemonic: Mnemonic,
+    bytes: &[u8],
+) -> Result<Instruction, DecodeError> {
     let word_operation = (bytes[0] & 0x1) != 0;
 
     let length = if word_operation { 3 } else { 2 };
 
     let data = if word_operation {
-        u16::from_le_bytes([bytes[1], bytes[2]])
+        word_at(bytes, 1)?
     } else {
-        bytes[1] as u16
+        byte_at(bytes, 1)? as u16
     };
 
-    let register_name = if word_operation {
-        RegisterName::AX
+    Ok(Instruction::new(
+        length,
+        InstructionCategory::ImmediateToAccumulator(mnemonic, data, accumulator(word_operation)),
+    ))
+}
+
+fn decode_memory_and_accumulator(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let word_operation = (bytes[0] & 0x1) != 0;
+    let accumulator_is_destination = (bytes[0] & 0x2) == 0;
+
+    let address = RegisterMemory::DirectAddress(word_at(bytes, 1)?);
+    let register = RegisterMemory::Register(accumulator(word_operation));
+
+    let instruction_category = if accumulator_is_destination {
+        InstructionCategory::RegisterMemoryAndRegister(Mnemonic::MOV, address, register)
     } else {
-        RegisterName::AL
+        InstructionCategory::RegisterMemoryAndRegister(Mnemonic::MOV, register, address)
     };
+    Ok(Instruction::new(3, instruction_category))
+}
 
-    Instruction {
-        length,
-        instruction_category: InstructionCategory::ImmediateToAccumulator(
-            mnemonic,
-            data,
-            register_name,
-        ),
+fn decode_register(mnemonic: Mnemonic, opcode: u8) -> Instruction {
+    let register = decode_register_name(opcode, true);
+
+    Instruction::new(
+        1,
+        InstructionCategory::SingleOperand(mnemonic, RegisterMemory::Register(register), true),
+    )
+}
+
+fn decode_segment_register(mnemonic: Mnemonic, opcode: u8) -> Instruction {
+    let register = match (opcode >> 3) & 0x3 {
+        0x0 => RegisterName::ES,
+        0x1 => RegisterName::CS,
+        0x2 => RegisterName::SS,
+        _ => RegisterName::DS,
+    };
+
+    Instruction::new(
+        1,
+        InstructionCategory::SingleOperand(mnemonic, RegisterMemory::Register(register), true),
+    )
+}
+
+fn decode_pop_register_memory(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let operand = decode_register_memory_operand(bytes, true)?;
+
+    if operand.reg_field != 0 {
+        return Err(invalid_mod_rm(bytes));
     }
+
+    Ok(Instruction::new(
+        operand.instruction_length,
+        InstructionCategory::SingleOperand(Mnemonic::POP, operand.register_memory, true),
+    ))
+}
+
+fn decode_shift(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let word_operation = (bytes[0] & 0x1) != 0;
+    let by_cl = (bytes[0] & 0x2) != 0;
+
+    let operand = decode_register_memory_operand(bytes, word_operation)?;
+    let mnemonic = match operand.reg_field {
+        0x0 => Mnemonic::ROL,
+        0x1 => Mnemonic::ROR,
+        0x2 => Mnemonic::RCL,
+        0x3 => Mnemonic::RCR,
+        0x4 => Mnemonic::SHL,
+        0x5 => Mnemonic::SHR,
+        0x7 => Mnemonic::SAR,
+        _ => return Err(invalid_mod_rm(bytes)),
+    };
+
+    Ok(Instruction::new(
+        operand.instruction_length,
+        InstructionCategory::Shift(mnemonic, operand.register_memory, word_operation, by_cl),
+    ))
 }
 
-fn decode_jump(mnemonic: Mnemonic, remaining_bytes: &[u8]) -> Instruction {
-    let increment = remaining_bytes[1] as i8;
+fn decode_group_3(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let word_operation = (bytes[0] & 0x1) != 0;
+
+    let operand = decode_register_memory_operand(bytes, word_operation)?;
+    let mnemonic = match operand.reg_field {
+        0x0 => {
+            return decode_immediate_to_register_memory(
+                Mnemonic::TEST,
+                word_operation,
+                false,
+                bytes,
+            )
+        }
+        0x2 => Mnemonic::NOT,
+        0x3 => Mnemonic::NEG,
+        0x4 => Mnemonic::MUL,
+        0x5 => Mnemonic::IMUL,
+        0x6 => Mnemonic::DIV,
+        0x7 => Mnemonic::IDIV,
+        _ => return Err(invalid_mod_rm(bytes)),
+    };
+
+    Ok(Instruction::new(
+        operand.instruction_length,
+        InstructionCategory::SingleOperand(mnemonic, operand.register_memory, word_operation),
+    ))
+}
 
-    Instruction {
-        length: 2,
-        instruction_category: InstructionCategory::Jump(mnemonic, increment),
+fn decode_group_4(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let operand = decode_register_memory_operand(bytes, false)?;
+    let mnemonic = match operand.reg_field {
+        0x0 => Mnemonic::INC,
+        0x1 => Mnemonic::DEC,
+        _ => return Err(invalid_mod_rm(bytes)),
+    };
+
+    Ok(Instruction::new(
+        operand.instruction_length,
+        InstructionCategory::SingleOperand(mnemonic, operand.register_memory, false),
+    ))
+}
+
+fn decode_group_5(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let operand = decode_register_memory_operand(bytes, true)?;
+    let instruction_category = match operand.reg_field {
+        0x0 => InstructionCategory::SingleOperand(Mnemonic::INC, operand.register_memory, true),
+        0x1 => InstructionCategory::SingleOperand(Mnemonic::DEC, operand.register_memory, true),
+        0x2 => InstructionCategory::SingleOperand(Mnemonic::CALL, operand.register_memory, true),
+        0x4 => InstructionCategory::SingleOperand(Mnemonic::JMP, operand.register_memory, true),
+        0x6 => InstructionCategory::SingleOperand(Mnemonic::PUSH, operand.register_memory, true),
+        0x3 | 0x5 if operand.register_memory.is_memory() => {
+            let mnemonic = if operand.reg_field == 0x3 {
+                Mnemonic::CALL
+            } else {
+                Mnemonic::JMP
+            };
+            InstructionCategory::IndirectFar(mnemonic, operand.register_memory)
+        }
+        _ => return Err(invalid_mod_rm(bytes)),
+    };
+
+    Ok(Instruction::new(
+        operand.instruction_length,
+        instruction_category,
+    ))
+}
+
+fn decode_escape(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let operand = decode_register_memory_operand(bytes, true)?;
+    let opcode = ((bytes[0] & 0x7) << 3) | operand.reg_field;
+
+    Ok(Instruction::new(
+        operand.instruction_length,
+        InstructionCategory::Escape(opcode, operand.register_memory),
+    ))
+}
+
+fn decode_fixed_port(mnemonic: Mnemonic, bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let word_operation = (bytes[0] & 0x1) != 0;
+
+    Ok(Instruction::new(
+        2,
+        InstructionCategory::FixedPort(mnemonic, byte_at(bytes, 1)?, accumulator(word_operation)),
+    ))
+}
+
+fn decode_variable_port(mnemonic: Mnemonic, opcode: u8) -> Instruction {
+    let word_operation = (opcode & 0x1) != 0;
+
+    Instruction::new(
+        1,
+        InstructionCategory::VariablePort(mnemonic, accumulator(word_operation)),
+    )
+}
+
+fn decode_string(mnemonic: Mnemonic, opcode: u8) -> Instruction {
+    let word_operation = (opcode & 0x1) != 0;
+
+    Instruction::new(
+        1,
+        InstructionCategory::StringManipulation(mnemonic, word_operation),
+    )
+}
+
+fn decode_immediate(mnemonic: Mnemonic, bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    if mnemonic == Mnemonic::INT {
+        Ok(Instruction::new(
+            2,
+            InstructionCategory::Immediate(mnemonic, u16::from(byte_at(bytes, 1)?)),
+        ))
+    } else {
+        Ok(Instruction::new(
+            3,
+            InstructionCategory::Immediate(mnemonic, word_at(bytes, 1)?),
+        ))
+    }
+}
+
+fn decode_ascii_adjust(mnemonic: Mnemonic, bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    // AAM and AAD carry the base as a second byte, which is always 10 for
+    // the documented forms.
+    let base = byte_at(bytes, 1)?;
+    if base == 10 {
+        Ok(Instruction::new(
+            2,
+            InstructionCategory::NoOperands(mnemonic),
+        ))
+    } else {
+        Ok(Instruction::new(
+            2,
+            InstructionCategory::Immediate(mnemonic, u16::from(base)),
+        ))
+    }
+}
+
+fn decode_no_operands(mnemonic: Mnemonic) -> Instruction {
+    Instruction::new(1, InstructionCategory::NoOperands(mnemonic))
+}
+
+fn decode_jump(mnemonic: Mnemonic, remaining_bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let increment = byte_at(remaining_bytes, 1)? as i8;
+
+    Ok(Instruction::new(
+        2,
+        InstructionCategory::Jump(mnemonic, increment),
+    ))
+}
+
+fn decode_near_jump(
+    mnemonic: Mnemonic,
+    remaining_bytes: &[u8],
+) -> Result<Instruction, DecodeError> {
+    let increment = word_at(remaining_bytes, 1)? as i16;
+
+    Ok(Instruction::new(
+        3,
+        InstructionCategory::NearJump(mnemonic, increment),
+    ))
+}
+
+fn decode_far_jump(mnemonic: Mnemonic, remaining_bytes: &[u8]) -> Result<Instruction, DecodeError> {
+    let segment = word_at(remaining_bytes, 3)?;
+    let offset = word_at(remaining_bytes, 1)?;
+
+    Ok(Instruction::new(
+        5,
+        InstructionCategory::FarJump(mnemonic, segment, offset),
+    ))
+}
+
+fn accumulator(word_operation: bool) -> RegisterName {
+    if word_operation {
+        RegisterName::AX
+    } else {
+        RegisterName::AL
     }
 }
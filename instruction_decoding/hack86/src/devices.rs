@@ -0,0 +1,142 @@
+use std::io::Write;
+use std::ops::{Range, RangeInclusive};
+
+use crate::bus::Device;
+use crate::memory::physical_address;
+
+pub const TEXT_VIDEO_SEGMENT: u16 = 0xB800;
+const TEXT_VIDEO_SIZE: usize = 0x8000;
+const COLUMNS: usize = 80;
+const ROWS: usize = 25;
+// Light grey on black.
+const DEFAULT_ATTRIBUTE: u8 = 0x07;
+
+// The colour text mode buffer: character/attribute byte pairs, 80x25 per page.
+pub struct TextVideo {
+    cells: Vec<u8>,
+}
+
+impl Default for TextVideo {
+    fn default() -> TextVideo {
+        TextVideo::new()
+    }
+}
+
+impl TextVideo {
+    // Starts out cleared to blanks, as the BIOS leaves it.
+    pub fn new() -> TextVideo {
+        TextVideo {
+            cells: [b' ', DEFAULT_ATTRIBUTE].repeat(TEXT_VIDEO_SIZE / 2),
+        }
+    }
+
+    // The first page, one line per row, without trailing blanks. Characters
+    // outside printable ASCII show as '.'.
+    pub fn text(&self) -> String {
+        let rows = self.cells[..COLUMNS * ROWS * 2]
+            .chunks(COLUMNS * 2)
+            .map(|row| {
+                let line = row
+                    .iter()
+                    .step_by(2)
+                    .map(|character| match character {
+                        0 => ' ',
+                        0x20..=0x7E => char::from(*character),
+                        _ => '.',
+                    })
+                    .collect::<String>();
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<String>>();
+        let used = rows
+            .iter()
+            .rposition(|row| !row.is_empty())
+            .map_or(0, |last| last + 1);
+        rows[..used].join("\n")
+    }
+}
+
+impl Device for TextVideo {
+    fn memory_range(&self) -> Option<Range<usize>> {
+        let start = physical_address(TEXT_VIDEO_SEGMENT, 0);
+        Some(start..start + TEXT_VIDEO_SIZE)
+    }
+
+    fn read(&self, offset: usize) -> u8 {
+        self.cells[offset]
+    }
+
+    fn write(&mut self, offset: usize, value: u8) {
+        self.cells[offset] = value;
+    }
+}
+
+pub const COM1: u16 = 0x3F8;
+const TRANSMIT: u16 = 0;
+const LINE_CONTROL: u16 = 3;
+const LINE_STATUS: u16 = 5;
+const DIVISOR_LATCH_ACCESS: u8 = 0x80;
+// Transmit holding register and transmitter both empty, no data received.
+const TRANSMITTER_IDLE: u8 = 0x60;
+
+// An 8250 UART whose transmitter writes straight to `output` and which never
+// receives anything. It is always ready to send, so programs that poll the
+// line status before each byte work unchanged. The first failed write is kept
+// for the host to check, and later bytes are dropped.
+pub struct SerialPort<W: Write> {
+    base: u16,
+    output: W,
+    registers: [u8; 8],
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> SerialPort<W> {
+    pub fn new(base: u16, output: W) -> SerialPort<W> {
+        SerialPort {
+            base,
+            output,
+            registers: [0; 8],
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&std::io::Error> {
+        self.error.as_ref()
+    }
+
+    fn divisor_latch(&self) -> bool {
+        self.registers[usize::from(LINE_CONTROL)] & DIVISOR_LATCH_ACCESS != 0
+    }
+}
+
+impl<W: Write + 'static> Device for SerialPort<W> {
+    // A port placed at the very top of the I/O space loses the registers that
+    // would lie past 0xFFFF.
+    fn ports(&self) -> Option<RangeInclusive<u16>> {
+        Some(self.base..=self.base.saturating_add(7))
+    }
+
+    fn input(&mut self, port: u16) -> u8 {
+        match port - self.base {
+            TRANSMIT if !self.divisor_latch() => 0,
+            LINE_STATUS => TRANSMITTER_IDLE,
+            register => self.registers[usize::from(register)],
+        }
+    }
+
+    fn output(&mut self, port: u16, value: u8) {
+        match port - self.base {
+            TRANSMIT if !self.divisor_latch() => {
+                if self.error.is_none() {
+                    self.error = self
+                        .output
+                        .write_all(&[value])
+                        .and_then(|_| self.output.flush())
+                        .err();
+                }
+            }
+            LINE_STATUS => {}
+            register => self.registers[usize::from(register)] = value,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::Write;
+
+use crate::decode::RegisterName;
+use crate::memory::Memory;
+use crate::simulate::{InterruptHandler, InterruptResult, LoadError, CPU};
+
+// The handful of DOS services that small test programs need to report
+// results and exit: INT 20h and a few INT 21h functions.
+pub struct DosServices<W: Write> {
+    output: W,
+}
+
+impl<W: Write> DosServices<W> {
+    pub fn new(output: W) -> DosServices<W> {
+        DosServices { output }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> InterruptResult {
+        match self
+            .output
+            .write_all(bytes)
+            .and_then(|_| self.output.flush())
+        {
+            Ok(()) => InterruptResult::Handled,
+            Err(error) => InterruptResult::OutputFailed(error),
+        }
+    }
+}
+
+impl<W: Write> InterruptHandler for DosServices<W> {
+    fn interrupt(&mut self, vector: u8, cpu: &mut CPU, memory: &mut Memory) -> InterruptResult {
+        match vector {
+            0x20 => return InterruptResult::Exit(0),
+            0x21 => {}
+            _ => return InterruptResult::Unhandled,
+        }
+
+        match cpu.get_register(&RegisterName::AH) {
+            0x00 => InterruptResult::Exit(0),
+            0x02 => {
+                let character = cpu.get_register(&RegisterName::DL) as u8;
+                self.write(&[character])
+            }
+            0x09 => {
+                let segment = cpu.get_register(&RegisterName::DS);
+                let mut offset = cpu.get_register(&RegisterName::DX);
+                let mut string = Vec::new();
+                // The string ends at the first '$', which we give up looking
+                // for after a whole segment.
+                for _ in 0..=u16::MAX {
+                    let byte = memory.read(segment, offset, false) as u8;
+                    if byte == b'$' {
+                        break;
+                    }
+                    string.push(byte);
+                    offset = offset.wrapping_add(1);
+                }
+                self.write(&string)
+            }
+            0x4C => InterruptResult::Exit(cpu.get_register(&RegisterName::AL) as u8),
+            _ => InterruptResult::Unhandled,
+        }
+    }
+}
+
+pub(crate) struct ExeHeader {
+    pub(crate) header_size: usize,
+    pub(crate) load_module_size: usize,
+    pub(crate) min_alloc: u16,
+    pub(crate) max_alloc: u16,
+    pub(crate) ss: u16,
+    pub(crate) sp: u16,
+    pub(crate) ip: u16,
+    pub(crate) cs: u16,
+    // (offset, segment) pairs, with the segment relative to the load segment.
+    pub(crate) relocations: Vec<(u16, u16)>,
+}
+
+impl ExeHeader {
+    pub(crate) fn parse(image: &[u8]) -> Result<ExeHeader, LoadError> {
+        let word = |offset: usize| -> Result<u16, LoadError> {
+            image
+                .get(offset..offset + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+                .ok_or(LoadError::InvalidExeHeader)
+        };
+
+        if !matches!(image.get(0..2), Some(b"MZ" | b"ZM")) {
+            return Err(LoadError::InvalidExeHeader);
+        }
+        let last_page_bytes = usize::from(word(0x02)?);
+        let pages = usize::from(word(0x04)?);
+        let relocation_count = usize::from(word(0x06)?);
+        let header_size = usize::from(word(0x08)?) * 16;
+        let relocation_table = usize::from(word(0x18)?);
+
+        // The last page is only partly used when the byte count is non-zero.
+        let file_size = match last_page_bytes {
+            0 => pages * 512,
+            _ => pages.saturating_sub(1) * 512 + last_page_bytes,
+        };
+        if file_size < header_size {
+            return Err(LoadError::InvalidExeHeader);
+        }
+        if image.len() < file_size {
+            return Err(LoadError::TruncatedExe {
+                expected: file_size,
+                actual: image.len(),
+            });
+        }
+
+        let relocations = (0..relocation_count)
+            .map(|index| {
+                let entry = relocation_table + index * 4;
+                Ok((word(entry)?, word(entry + 2)?))
+            })
+            .collect::<Result<Vec<(u16, u16)>, LoadError>>()?;
+
+        Ok(ExeHeader {
+            header_size,
+            load_module_size: file_size - header_size,
+            min_alloc: word(0x0A)?,
+            max_alloc: word(0x0C)?,
+            ss: word(0x0E)?,
+            sp: word(0x10)?,
+            ip: word(0x14)?,
+            cs: word(0x16)?,
+            relocations,
+        })
+    }
+}
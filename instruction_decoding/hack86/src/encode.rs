@@ -0,0 +1,1841 @@
+use std::collections::HashMap;
+
+use crate::decode::{
+    Instruction, InstructionCategory, Mnemonic, RegisterMemory, RegisterName, Repeat,
+};
+
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum EncodeError {
+    InvalidOperands,
+    ImmediateOutOfRange(i64),
+    JumpOutOfRange(i64),
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::InvalidOperands => write!(f, "invalid combination of operands"),
+            EncodeError::ImmediateOutOfRange(value) => {
+                write!(f, "immediate {} does not fit the operand size", value)
+            }
+            EncodeError::JumpOutOfRange(distance) => {
+                write!(f, "jump distance {} is out of range", distance)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum AssembleError {
+    Syntax { line: usize, message: String },
+    UndefinedLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+    Encode { line: usize, error: EncodeError },
+    NoConvergence,
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssembleError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            AssembleError::UndefinedLabel { line, label } => {
+                write!(f, "line {}: undefined label `{}`", line, label)
+            }
+            AssembleError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: label `{}` is already defined", line, label)
+            }
+            AssembleError::Encode { line, error } => write!(f, "line {}: {}", line, error),
+            AssembleError::NoConvergence => write!(f, "jump sizes did not converge"),
+        }
+    }
+}
+
+/// Encodes an instruction, choosing the shortest encoding NASM would.
+pub fn encode_instruction(instruction: &Instruction) -> Result<Vec<u8>, EncodeError> {
+    let mut bytes = Vec::new();
+
+    if instruction.lock {
+        bytes.push(0b11110000);
+    }
+    match instruction.repeat {
+        Some(Repeat::Repne) => bytes.push(0b11110010),
+        Some(Repeat::Rep) => bytes.push(0b11110011),
+        None => {}
+    }
+    if let Some(segment) = &instruction.segment_override {
+        let segment = segment_register_number(segment).ok_or(EncodeError::InvalidOperands)?;
+        bytes.push(0b00100110 | (segment << 3));
+    }
+
+    encode_operation(&instruction.instruction_category, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn encode_operation(
+    instruction_category: &InstructionCategory,
+    bytes: &mut Vec<u8>,
+) -> Result<(), EncodeError> {
+    match instruction_category {
+        InstructionCategory::RegisterMemoryAndRegister(mnemonic, src, dest) => {
+            encode_register_memory_and_register(mnemonic, src, dest, bytes)
+        }
+        InstructionCategory::ImmediateToRegister(Mnemonic::MOV, immediate, register) => {
+            let (register, word_operation) =
+                register_number(register).ok_or(EncodeError::InvalidOperands)?;
+            bytes.push(0b10110000 | (u8::from(word_operation) << 3) | register);
+            push_immediate(bytes, *immediate, word_operation);
+            Ok(())
+        }
+        InstructionCategory::ImmediateToRegister(_, _, _) => Err(EncodeError::InvalidOperands),
+        InstructionCategory::ImmediateToRegisterMemory(
+            mnemonic,
+            immediate,
+            register_memory,
+            word_operation,
+        ) => encode_immediate_to_register_memory(
+            mnemonic,
+            *immediate,
+            register_memory,
+            *word_operation,
+            bytes,
+        ),
+        InstructionCategory::ImmediateToAccumulator(mnemonic, immediate, register) => {
+            let word_operation = *register == RegisterName::AX;
+            encode_immediate_to_register_memory(
+                mnemonic,
+                *immediate,
+                &RegisterMemory::Register(accumulator(word_operation)),
+                word_operation,
+                bytes,
+            )
+        }
+        InstructionCategory::SingleOperand(mnemonic, operand, word_operation) => {
+            encode_single_operand(mnemonic, operand, *word_operation, bytes)
+        }
+        InstructionCategory::Shift(mnemonic, operand, word_operation, by_cl) => {
+            let operation = match mnemonic {
+                Mnemonic::ROL => 0x0,
+                Mnemonic::ROR => 0x1,
+                Mnemonic::RCL => 0x2,
+                Mnemonic::RCR => 0x3,
+                Mnemonic::SHL => 0x4,
+                Mnemonic::SHR => 0x5,
+                Mnemonic::SAR => 0x7,
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            check_operand_width(operand, *word_operation)?;
+            bytes.push(0b11010000 | (u8::from(*by_cl) << 1) | u8::from(*word_operation));
+            encode_mod_rm(bytes, operation, operand)
+        }
+        InstructionCategory::NoOperands(mnemonic) => {
+            let opcode = match mnemonic {
+                Mnemonic::DAA => 0b00100111,
+                Mnemonic::DAS => 0b00101111,
+                Mnemonic::AAA => 0b00110111,
+                Mnemonic::AAS => 0b00111111,
+                Mnemonic::NOP => 0b10010000,
+                Mnemonic::CBW => 0b10011000,
+                Mnemonic::CWD => 0b10011001,
+                Mnemonic::WAIT => 0b10011011,
+                Mnemonic::PUSHF => 0b10011100,
+                Mnemonic::POPF => 0b10011101,
+                Mnemonic::SAHF => 0b10011110,
+                Mnemonic::LAHF => 0b10011111,
+                Mnemonic::RET => 0b11000011,
+                Mnemonic::RETF => 0b11001011,
+                Mnemonic::INT3 => 0b11001100,
+                Mnemonic::INTO => 0b11001110,
+                Mnemonic::IRET => 0b11001111,
+                Mnemonic::XLAT => 0b11010111,
+                Mnemonic::HLT => 0b11110100,
+                Mnemonic::CMC => 0b11110101,
+                Mnemonic::CLC => 0b11111000,
+                Mnemonic::STC => 0b11111001,
+                Mnemonic::CLI => 0b11111010,
+                Mnemonic::STI => 0b11111011,
+                Mnemonic::CLD => 0b11111100,
+                Mnemonic::STD => 0b11111101,
+                Mnemonic::AAM => {
+                    bytes.extend([0b11010100, 10]);
+                    return Ok(());
+                }
+                Mnemonic::AAD => {
+                    bytes.extend([0b11010101, 10]);
+                    return Ok(());
+                }
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            bytes.push(opcode);
+            Ok(())
+        }
+        InstructionCategory::Immediate(mnemonic, immediate) => {
+            match mnemonic {
+                Mnemonic::INT => bytes.push(0b11001101),
+                Mnemonic::AAM => bytes.push(0b11010100),
+                Mnemonic::AAD => bytes.push(0b11010101),
+                Mnemonic::RET => bytes.push(0b11000010),
+                Mnemonic::RETF => bytes.push(0b11001010),
+                _ => return Err(EncodeError::InvalidOperands),
+            }
+            match mnemonic {
+                Mnemonic::RET | Mnemonic::RETF => push_immediate(bytes, *immediate, true),
+                _ => bytes.push(byte_immediate(*immediate)?),
+            }
+            Ok(())
+        }
+        InstructionCategory::StringManipulation(mnemonic, word_operation) => {
+            let opcode = match mnemonic {
+                Mnemonic::MOVS => 0b10100100,
+                Mnemonic::CMPS => 0b10100110,
+                Mnemonic::STOS => 0b10101010,
+                Mnemonic::LODS => 0b10101100,
+                Mnemonic::SCAS => 0b10101110,
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            bytes.push(opcode | u8::from(*word_operation));
+            Ok(())
+        }
+        InstructionCategory::FixedPort(mnemonic, port, register) => {
+            let opcode = match mnemonic {
+                Mnemonic::IN => 0b11100100,
+                Mnemonic::OUT => 0b11100110,
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            bytes.extend([opcode | accumulator_width(register)?, *port]);
+            Ok(())
+        }
+        InstructionCategory::VariablePort(mnemonic, register) => {
+            let opcode = match mnemonic {
+                Mnemonic::IN => 0b11101100,
+                Mnemonic::OUT => 0b11101110,
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            bytes.push(opcode | accumulator_width(register)?);
+            Ok(())
+        }
+        InstructionCategory::Jump(mnemonic, increment) => {
+            bytes.extend([jump_opcode(mnemonic)?, *increment as u8]);
+            Ok(())
+        }
+        InstructionCategory::NearJump(mnemonic, increment) => {
+            match mnemonic {
+                Mnemonic::CALL => bytes.push(0b11101000),
+                Mnemonic::JMP => bytes.push(0b11101001),
+                _ => return Err(EncodeError::InvalidOperands),
+            }
+            bytes.extend(increment.to_le_bytes());
+            Ok(())
+        }
+        InstructionCategory::FarJump(mnemonic, segment, offset) => {
+            match mnemonic {
+                Mnemonic::CALL => bytes.push(0b10011010),
+                Mnemonic::JMP => bytes.push(0b11101010),
+                _ => return Err(EncodeError::InvalidOperands),
+            }
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(segment.to_le_bytes());
+            Ok(())
+        }
+        InstructionCategory::IndirectFar(mnemonic, operand) => {
+            let operation = match mnemonic {
+                Mnemonic::CALL => 0x3,
+                Mnemonic::JMP => 0x5,
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            if !is_memory(operand) {
+                return Err(EncodeError::InvalidOperands);
+            }
+            bytes.push(0b11111111);
+            encode_mod_rm(bytes, operation, operand)
+        }
+        InstructionCategory::Escape(opcode, operand) => {
+            if *opcode > 0x3F {
+                return Err(EncodeError::InvalidOperands);
+            }
+            bytes.push(0b11011000 | (opcode >> 3));
+            encode_mod_rm(bytes, opcode & 0x7, operand)
+        }
+    }
+}
+
+fn encode_register_memory_and_register(
+    mnemonic: &Mnemonic,
+    src: &RegisterMemory,
+    dest: &RegisterMemory,
+    bytes: &mut Vec<u8>,
+) -> Result<(), EncodeError> {
+    if *mnemonic == Mnemonic::MOV {
+        if let RegisterMemory::Register(segment) = src {
+            if let Some(segment) = segment_register_number(segment) {
+                check_operand_width(dest, true)?;
+                bytes.push(0b10001100);
+                return encode_mod_rm(bytes, segment, dest);
+            }
+        }
+        if let RegisterMemory::Register(segment) = dest {
+            if let Some(segment) = segment_register_number(segment) {
+                check_operand_width(src, true)?;
+                bytes.push(0b10001110);
+                return encode_mod_rm(bytes, segment, src);
+            }
+        }
+        match (src, dest) {
+            (RegisterMemory::DirectAddress(address), RegisterMemory::Register(register))
+            | (RegisterMemory::Register(register), RegisterMemory::DirectAddress(address))
+                if matches!(register, RegisterName::AL | RegisterName::AX) =>
+            {
+                let to_memory = matches!(dest, RegisterMemory::DirectAddress(_));
+                bytes.push(0b10100000 | (u8::from(to_memory) << 1) | accumulator_width(register)?);
+                bytes.extend(address.to_le_bytes());
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    match mnemonic {
+        Mnemonic::LEA | Mnemonic::LDS | Mnemonic::LES => {
+            let RegisterMemory::Register(register) = dest else {
+                return Err(EncodeError::InvalidOperands);
+            };
+            let (register, true) = register_number(register).ok_or(EncodeError::InvalidOperands)?
+            else {
+                return Err(EncodeError::InvalidOperands);
+            };
+            if !is_memory(src) {
+                return Err(EncodeError::InvalidOperands);
+            }
+            bytes.push(match mnemonic {
+                Mnemonic::LEA => 0b10001101,
+                Mnemonic::LDS => 0b11000101,
+                _ => 0b11000100,
+            });
+            encode_mod_rm(bytes, register, src)
+        }
+        Mnemonic::XCHG => {
+            if let (RegisterMemory::Register(a), RegisterMemory::Register(b)) = (src, dest) {
+                if *a == RegisterName::AX || *b == RegisterName::AX {
+                    let other = if *a == RegisterName::AX { b } else { a };
+                    if let Some((register, true)) = register_number(other) {
+                        bytes.push(0b10010000 | register);
+                        return Ok(());
+                    }
+                }
+            }
+            let (register, register_memory) = match (src, dest) {
+                (_, RegisterMemory::Register(register)) => (register, src),
+                (RegisterMemory::Register(register), _) => (register, dest),
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            let (register, word_operation) =
+                register_number(register).ok_or(EncodeError::InvalidOperands)?;
+            check_operand_width(register_memory, word_operation)?;
+            bytes.push(0b10000110 | u8::from(word_operation));
+            encode_mod_rm(bytes, register, register_memory)
+        }
+        Mnemonic::TEST => {
+            let (register, register_memory) = match (src, dest) {
+                (RegisterMemory::Register(register), _) => (register, dest),
+                (_, RegisterMemory::Register(register)) => (register, src),
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            let (register, word_operation) =
+                register_number(register).ok_or(EncodeError::InvalidOperands)?;
+            check_operand_width(register_memory, word_operation)?;
+            bytes.push(0b10000100 | u8::from(word_operation));
+            encode_mod_rm(bytes, register, register_memory)
+        }
+        _ => {
+            let opcode = match mnemonic {
+                Mnemonic::MOV => 0b10001000,
+                _ => arithmetic_operation(mnemonic)? << 3,
+            };
+            let (register, register_memory, reg_is_destination) = match (src, dest) {
+                (RegisterMemory::Register(register), _) => (register, dest, false),
+                (_, RegisterMemory::Register(register)) => (register, src, true),
+                _ => return Err(EncodeError::InvalidOperands),
+            };
+            let (register, word_operation) =
+                register_number(register).ok_or(EncodeError::InvalidOperands)?;
+            check_operand_width(register_memory, word_operation)?;
+            bytes.push(opcode | (u8::from(reg_is_destination) << 1) | u8::from(word_operation));
+            encode_mod_rm(bytes, register, register_memory)
+        }
+    }
+}
+
+fn encode_immediate_to_register_memory(
+    mnemonic: &Mnemonic,
+    immediate: u16,
+    register_memory: &RegisterMemory,
+    word_operation: bool,
+    bytes: &mut Vec<u8>,
+) -> Result<(), EncodeError> {
+    check_operand_width(register_memory, word_operation)?;
+    if !word_operation {
+        byte_immediate(immediate)?;
+    }
+    let is_accumulator = matches!(
+        register_memory,
+        RegisterMemory::Register(RegisterName::AL | RegisterName::AX)
+    );
+    let sign_extends = (immediate as i16) >= -128 && (immediate as i16) <= 127;
+
+    match mnemonic {
+        Mnemonic::MOV => {
+            if let RegisterMemory::Register(register) = register_memory {
+                let (register, _) =
+                    register_number(register).ok_or(EncodeError::InvalidOperands)?;
+                bytes.push(0b10110000 | (u8::from(word_operation) << 3) | register);
+            } else {
+                bytes.push(0b11000110 | u8::from(word_operation));
+                encode_mod_rm(bytes, 0, register_memory)?;
+            }
+            push_immediate(bytes, immediate, word_operation);
+        }
+        Mnemonic::TEST => {
+            if is_accumulator {
+                bytes.push(0b10101000 | u8::from(word_operation));
+            } else {
+                bytes.push(0b11110110 | u8::from(word_operation));
+                encode_mod_rm(bytes, 0, register_memory)?;
+            }
+            push_immediate(bytes, immediate, word_operation);
+        }
+        _ => {
+            let operation = arithmetic_operation(mnemonic)?;
+            if is_accumulator && !(word_operation && sign_extends) {
+                bytes.push((operation << 3) | 0b100 | u8::from(word_operation));
+                push_immediate(bytes, immediate, word_operation);
+            } else if word_operation && sign_extends {
+                bytes.push(0b10000011);
+                encode_mod_rm(bytes, operation, register_memory)?;
+                bytes.push(immediate as u8);
+            } else {
+                bytes.push(0b10000000 | u8::from(word_operation));
+                encode_mod_rm(bytes, operation, register_memory)?;
+                push_immediate(bytes, immediate, word_operation);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_single_operand(
+    mnemonic: &Mnemonic,
+    operand: &RegisterMemory,
+    word_operation: bool,
+    bytes: &mut Vec<u8>,
+) -> Result<(), EncodeError> {
+    if let RegisterMemory::Register(register) = operand {
+        if let Some(segment) = segment_register_number(register) {
+            match mnemonic {
+                Mnemonic::PUSH => bytes.push(0b00000110 | (segment << 3)),
+                Mnemonic::POP if *register != RegisterName::CS => {
+                    bytes.push(0b00000111 | (segment << 3))
+                }
+                _ => return Err(EncodeError::InvalidOperands),
+            }
+            return Ok(());
+        }
+        if let Some((register, true)) = register_number(register) {
+            let opcode = match mnemonic {
+                Mnemonic::INC => Some(0b01000000),
+                Mnemonic::DEC => Some(0b01001000),
+                Mnemonic::PUSH => Some(0b01010000),
+                Mnemonic::POP => Some(0b01011000),
+                _ => None,
+            };
+            if let Some(opcode) = opcode {
+                bytes.push(opcode | register);
+                return Ok(());
+            }
+        }
+    }
+
+    check_operand_width(operand, word_operation)?;
+    let (opcode, operation) = match mnemonic {
+        Mnemonic::INC => (0b11111110 | u8::from(word_operation), 0x0),
+        Mnemonic::DEC => (0b11111110 | u8::from(word_operation), 0x1),
+        Mnemonic::NOT => (0b11110110 | u8::from(word_operation), 0x2),
+        Mnemonic::NEG => (0b11110110 | u8::from(word_operation), 0x3),
+        Mnemonic::MUL => (0b11110110 | u8::from(word_operation), 0x4),
+        Mnemonic::IMUL => (0b11110110 | u8::from(word_operation), 0x5),
+        Mnemonic::DIV => (0b11110110 | u8::from(word_operation), 0x6),
+        Mnemonic::IDIV => (0b11110110 | u8::from(word_operation), 0x7),
+        Mnemonic::CALL if word_operation => (0b11111111, 0x2),
+        Mnemonic::JMP if word_operation => (0b11111111, 0x4),
+        Mnemonic::PUSH if word_operation => (0b11111111, 0x6),
+        Mnemonic::POP if word_operation => (0b10001111, 0x0),
+        _ => return Err(EncodeError::InvalidOperands),
+    };
+    bytes.push(opcode);
+    encode_mod_rm(bytes, operation, operand)
+}
+
+fn encode_mod_rm(
+    bytes: &mut Vec<u8>,
+    reg_field: u8,
+    register_memory: &RegisterMemory,
+) -> Result<(), EncodeError> {
+    let (register_memory_field, displacement) = match register_memory {
+        RegisterMemory::Register(register) => {
+            let (register, _) = register_number(register).ok_or(EncodeError::InvalidOperands)?;
+            bytes.push(0b11000000 | (reg_field << 3) | register);
+            return Ok(());
+        }
+        RegisterMemory::DirectAddress(address) => {
+            bytes.push((reg_field << 3) | 0b110);
+            bytes.extend(address.to_le_bytes());
+            return Ok(());
+        }
+        RegisterMemory::RegisterAddress(RegisterName::BP) => (0b110, Some(0)),
+        RegisterMemory::RegisterAddress(register) => (base_register_number(register)?, None),
+        RegisterMemory::RegisterAddressDisplacement(RegisterName::BP, displacement) => {
+            (0b110, Some(*displacement))
+        }
+        RegisterMemory::RegisterAddressDisplacement(register, displacement) => {
+            (base_register_number(register)?, Some(*displacement))
+        }
+        RegisterMemory::RegisterAddressOffset(register, offset_register) => {
+            (base_index_register_number(register, offset_register)?, None)
+        }
+        RegisterMemory::RegisterAddressOffsetDisplacement(
+            register,
+            offset_register,
+            displacement,
+        ) => (
+            base_index_register_number(register, offset_register)?,
+            Some(*displacement),
+        ),
+    };
+
+    match displacement {
+        None | Some(0) if register_memory_field != 0b110 => {
+            bytes.push((reg_field << 3) | register_memory_field);
+        }
+        Some(displacement) if (-128..=127).contains(&displacement) => {
+            bytes.push(0b01000000 | (reg_field << 3) | register_memory_field);
+            bytes.push(displacement as u8);
+        }
+        Some(displacement) => {
+            bytes.push(0b10000000 | (reg_field << 3) | register_memory_field);
+            bytes.extend(displacement.to_le_bytes());
+        }
+        None => return Err(EncodeError::InvalidOperands),
+    }
+    Ok(())
+}
+
+fn register_number(register: &RegisterName) -> Option<(u8, bool)> {
+    match register {
+        RegisterName::AL => Some((0x0, false)),
+        RegisterName::CL => Some((0x1, false)),
+        RegisterName::DL => Some((0x2, false)),
+        RegisterName::BL => Some((0x3, false)),
+        RegisterName::AH => Some((0x4, false)),
+        RegisterName::CH => Some((0x5, false)),
+        RegisterName::DH => Some((0x6, false)),
+        RegisterName::BH => Some((0x7, false)),
+        RegisterName::AX => Some((0x0, true)),
+        RegisterName::CX => Some((0x1, true)),
+        RegisterName::DX => Some((0x2, true)),
+        RegisterName::BX => Some((0x3, true)),
+        RegisterName::SP => Some((0x4, true)),
+        RegisterName::BP => Some((0x5, true)),
+        RegisterName::SI => Some((0x6, true)),
+        RegisterName::DI => Some((0x7, true)),
+        _ => None,
+    }
+}
+
+fn segment_register_number(register: &RegisterName) -> Option<u8> {
+    match register {
+        RegisterName::ES => Some(0x0),
+        RegisterName::CS => Some(0x1),
+        RegisterName::SS => Some(0x2),
+        RegisterName::DS => Some(0x3),
+        _ => None,
+    }
+}
+
+fn base_register_number(register: &RegisterName) -> Result<u8, EncodeError> {
+    match register {
+        RegisterName::SI => Ok(0b100),
+        RegisterName::DI => Ok(0b101),
+        RegisterName::BX => Ok(0b111),
+        _ => Err(EncodeError::InvalidOperands),
+    }
+}
+
+fn base_index_register_number(
+    register: &RegisterName,
+    offset_register: &RegisterName,
+) -> Result<u8, EncodeError> {
+    match (register, offset_register) {
+        (RegisterName::BX, RegisterName::SI) => Ok(0b000),
+        (RegisterName::BX, RegisterName::DI) => Ok(0b001),
+        (RegisterName::BP, RegisterName::SI) => Ok(0b010),
+        (RegisterName::BP, RegisterName::DI) => Ok(0b011),
+        _ => Err(EncodeError::InvalidOperands),
+    }
+}
+
+fn arithmetic_operation(mnemonic: &Mnemonic) -> Result<u8, EncodeError> {
+    match mnemonic {
+        Mnemonic::ADD => Ok(0x0),
+        Mnemonic::OR => Ok(0x1),
+        Mnemonic::ADC => Ok(0x2),
+        Mnemonic::SBB => Ok(0x3),
+        Mnemonic::AND => Ok(0x4),
+        Mnemonic::SUB => Ok(0x5),
+        Mnemonic::XOR => Ok(0x6),
+        Mnemonic::CMP => Ok(0x7),
+        _ => Err(EncodeError::InvalidOperands),
+    }
+}
+
+fn jump_opcode(mnemonic: &Mnemonic) -> Result<u8, EncodeError> {
+    match mnemonic {
+        Mnemonic::JO => Ok(0b01110000),
+        Mnemonic::JNO => Ok(0b01110001),
+        Mnemonic::JC => Ok(0b01110010),
+        Mnemonic::JNC => Ok(0b01110011),
+        Mnemonic::JZ => Ok(0b01110100),
+        Mnemonic::JNZ => Ok(0b01110101),
+        Mnemonic::JNA => Ok(0b01110110),
+        Mnemonic::JA => Ok(0b01110111),
+        Mnemonic::JS => Ok(0b01111000),
+        Mnemonic::JNS => Ok(0b01111001),
+        Mnemonic::JPE => Ok(0b01111010),
+        Mnemonic::JPO => Ok(0b01111011),
+        Mnemonic::JL => Ok(0b01111100),
+        Mnemonic::JNL => Ok(0b01111101),
+        Mnemonic::JNG => Ok(0b01111110),
+        Mnemonic::JG => Ok(0b01111111),
+        Mnemonic::LOOPNE => Ok(0b11100000),
+        Mnemonic::LOOPE => Ok(0b11100001),
+        Mnemonic::LOOP => Ok(0b11100010),
+        Mnemonic::JCXZ => Ok(0b11100011),
+        Mnemonic::JMP => Ok(0b11101011),
+        _ => Err(EncodeError::InvalidOperands),
+    }
+}
+
+fn check_operand_width(
+    register_memory: &RegisterMemory,
+    word_operation: bool,
+) -> Result<(), EncodeError> {
+    match register_memory {
+        RegisterMemory::Register(register) => match register_number(register) {
+            Some((_, word_register)) if word_register == word_operation => Ok(()),
+            _ => Err(EncodeError::InvalidOperands),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn accumulator_width(register: &RegisterName) -> Result<u8, EncodeError> {
+    match register {
+        RegisterName::AL => Ok(0),
+        RegisterName::AX => Ok(1),
+        _ => Err(EncodeError::InvalidOperands),
+    }
+}
+
+fn accumulator(word_operation: bool) -> RegisterName {
+    if word_operation {
+        RegisterName::AX
+    } else {
+        RegisterName::AL
+    }
+}
+
+fn is_memory(register_memory: &RegisterMemory) -> bool {
+    !matches!(register_memory, RegisterMemory::Register(_))
+}
+
+fn byte_immediate(immediate: u16) -> Result<u8, EncodeError> {
+    if immediate <= 0xFF || immediate >= 0xFF80 {
+        Ok(immediate as u8)
+    } else {
+        Err(EncodeError::ImmediateOutOfRange(i64::from(immediate)))
+    }
+}
+
+fn push_immediate(bytes: &mut Vec<u8>, immediate: u16, word_operation: bool) {
+    if word_operation {
+        bytes.extend(immediate.to_le_bytes());
+    } else {
+        bytes.push(immediate as u8);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(i64),
+    String(Vec<u8>),
+    Punctuation(char),
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(i64),
+    Label(String),
+    Here,
+    Negate(Box<Expression>),
+    Binary(char, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Modifier {
+    Byte,
+    Word,
+    Short,
+    Near,
+    Far,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(RegisterName),
+    Immediate(Expression),
+    Memory {
+        segment: Option<RegisterName>,
+        registers: Vec<RegisterName>,
+        displacement: Option<Expression>,
+    },
+    FarPointer(Expression, Expression),
+}
+
+#[derive(Debug, Clone)]
+struct ParsedOperand {
+    modifier: Option<Modifier>,
+    operand: Operand,
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Instruction {
+        lock: bool,
+        repeat: Option<Repeat>,
+        segment_override: Option<RegisterName>,
+        mnemonic: String,
+        operands: Vec<ParsedOperand>,
+    },
+    Data {
+        word: bool,
+        items: Vec<DataItem>,
+    },
+    Origin(i64),
+}
+
+#[derive(Debug, Clone)]
+enum DataItem {
+    Bytes(Vec<u8>),
+    Value(Expression),
+}
+
+struct Line {
+    number: usize,
+    label: Option<String>,
+    statement: Option<Statement>,
+}
+
+struct Context<'a> {
+    labels: &'a HashMap<String, i64>,
+    address: i64,
+    line: usize,
+    strict: bool,
+}
+
+/// Assembles NASM-style 16-bit source into a flat binary.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| parse_line(index + 1, text))
+        .collect::<Result<Vec<Line>, AssembleError>>()?;
+
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    for line in &lines {
+        if let Some(label) = &line.label {
+            if labels.insert(label.clone(), 0).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line: line.number,
+                    label: label.clone(),
+                });
+            }
+        }
+    }
+
+    let mut origins = lines.iter().filter_map(|line| match &line.statement {
+        Some(Statement::Origin(origin)) => Some((line.number, *origin)),
+        _ => None,
+    });
+    let origin = match (origins.next(), origins.next()) {
+        (_, Some((line, _))) => return Err(syntax_error(line, "only one `org` is allowed")),
+        (Some((_, origin)), None) => origin,
+        (None, None) => 0,
+    };
+
+    // Jumps start out short. The labels are settled for the current jump sizes
+    // before any jump is widened, so a jump only becomes near when it cannot
+    // reach its target in a consistent layout; widening only moves labels
+    // further apart, so the layout settles after a handful of passes.
+    let mut near_jumps = vec![false; lines.len()];
+    for _ in 0..32 {
+        let (new_labels, needs_near) = layout(&lines, origin, &labels, &near_jumps)?;
+        if new_labels != labels {
+            labels = new_labels;
+            continue;
+        }
+        let mut widened = false;
+        for (near_jump, needs_near) in near_jumps.iter_mut().zip(needs_near) {
+            if needs_near && !*near_jump {
+                *near_jump = true;
+                widened = true;
+            }
+        }
+        if !widened {
+            let mut output = Vec::new();
+            for (index, line) in lines.iter().enumerate() {
+                if let Some(statement) = &line.statement {
+                    let context = Context {
+                        labels: &labels,
+                        address: origin + output.len() as i64,
+                        line: line.number,
+                        strict: true,
+                    };
+                    let (bytes, _) = assemble_statement(statement, &context, near_jumps[index])?;
+                    output.extend(bytes);
+                }
+            }
+            return Ok(output);
+        }
+    }
+
+    Err(AssembleError::NoConvergence)
+}
+
+// Lays the program out once with the given label addresses, returning where
+// the labels land and which short jumps cannot reach their targets.
+fn layout(
+    lines: &[Line],
+    origin: i64,
+    labels: &HashMap<String, i64>,
+    near_jumps: &[bool],
+) -> Result<(HashMap<String, i64>, Vec<bool>), AssembleError> {
+    let mut new_labels = HashMap::new();
+    let mut needs_near = vec![false; lines.len()];
+    let mut address = origin;
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(label) = &line.label {
+            new_labels.insert(label.clone(), address);
+        }
+        if let Some(statement) = &line.statement {
+            let context = Context {
+                labels,
+                address,
+                line: line.number,
+                strict: false,
+            };
+            let (bytes, near) = assemble_statement(statement, &context, near_jumps[index])?;
+            needs_near[index] = near;
+            address += bytes.len() as i64;
+        }
+    }
+    Ok((new_labels, needs_near))
+}
+
+fn syntax_error(line: usize, message: &str) -> AssembleError {
+    AssembleError::Syntax {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, AssembleError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut string = Vec::new();
+            loop {
+                match chars.next() {
+                    Some(end) if end == c => break,
+                    Some(ch) => {
+                        let mut buffer = [0; 4];
+                        string.extend(ch.encode_utf8(&mut buffer).as_bytes());
+                    }
+                    None => return Err(syntax_error(line, "unterminated string")),
+                }
+            }
+            tokens.push(Token::String(string));
+        } else if c.is_ascii_digit() {
+            let mut word = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch.is_ascii_alphanumeric() || ch == '_' {
+                    word.push(ch);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Number(parse_number(line, &word)?));
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '$' || c == '@' {
+            let mut word = String::new();
+            while let Some(&ch) = chars.peek() {
+                if ch.is_ascii_alphanumeric() || matches!(ch, '_' | '.' | '$' | '@' | '?') {
+                    word.push(ch);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Identifier(word));
+        } else if "[],:+-*/()".contains(c) {
+            tokens.push(Token::Punctuation(c));
+            chars.next();
+        } else {
+            return Err(syntax_error(line, &format!("unexpected character `{}`", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(line: usize, word: &str) -> Result<i64, AssembleError> {
+    let word = word.replace('_', "").to_ascii_lowercase();
+    let parsed = if let Some(hex) = word.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = word.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else if let Some(hex) = word.strip_suffix('h') {
+        i64::from_str_radix(hex, 16)
+    } else {
+        word.parse()
+    };
+    parsed.map_err(|_| syntax_error(line, &format!("invalid number `{}`", word)))
+}
+
+fn parse_line(number: usize, text: &str) -> Result<Line, AssembleError> {
+    let mut tokens = tokenize(number, text)?;
+
+    let mut label = None;
+    if let [Token::Identifier(name), Token::Punctuation(':'), ..] = tokens.as_slice() {
+        if parse_register(name).is_none() {
+            label = Some(name.clone());
+            tokens.drain(..2);
+        }
+    }
+
+    let statement = if tokens.is_empty() {
+        None
+    } else {
+        parse_statement(number, &tokens)?
+    };
+
+    Ok(Line {
+        number,
+        label,
+        statement,
+    })
+}
+
+fn parse_statement(line: usize, tokens: &[Token]) -> Result<Option<Statement>, AssembleError> {
+    let mut lock = false;
+    let mut repeat = None;
+    let mut segment_override = None;
+
+    let mut position = 0;
+    let mnemonic = loop {
+        let Some(Token::Identifier(word)) = tokens.get(position) else {
+            return Err(syntax_error(line, "expected an instruction"));
+        };
+        position += 1;
+        let word = word.to_ascii_lowercase();
+        match word.as_str() {
+            "lock" => lock = true,
+            "rep" | "repe" | "repz" => repeat = Some(Repeat::Rep),
+            "repne" | "repnz" => repeat = Some(Repeat::Repne),
+            "es" | "cs" | "ss" | "ds" => segment_override = parse_register(&word),
+            _ => break word,
+        }
+    };
+
+    let operand_tokens = split_operands(&tokens[position..]);
+
+    match mnemonic.as_str() {
+        "bits" => {
+            if let [[Token::Number(16)]] = operand_tokens.as_slice() {
+                return Ok(None);
+            }
+            return Err(syntax_error(line, "only `bits 16` is supported"));
+        }
+        "org" => {
+            if let [[Token::Number(origin)]] = operand_tokens.as_slice() {
+                return Ok(Some(Statement::Origin(*origin)));
+            }
+            return Err(syntax_error(line, "`org` takes a single number"));
+        }
+        "db" | "dw" => {
+            let items = operand_tokens
+                .iter()
+                .map(|item| match item {
+                    [Token::String(bytes)] if bytes.len() != 1 => {
+                        Ok(DataItem::Bytes(bytes.clone()))
+                    }
+                    _ => Ok(DataItem::Value(parse_expression(line, item)?)),
+                })
+                .collect::<Result<Vec<DataItem>, AssembleError>>()?;
+            return Ok(Some(Statement::Data {
+                word: mnemonic == "dw",
+                items,
+            }));
+        }
+        _ => {}
+    }
+
+    let operands = operand_tokens
+        .iter()
+        .map(|operand| parse_operand(line, operand))
+        .collect::<Result<Vec<ParsedOperand>, AssembleError>>()?;
+
+    Ok(Some(Statement::Instruction {
+        lock,
+        repeat,
+        segment_override,
+        mnemonic,
+        operands,
+    }))
+}
+
+fn split_operands(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punctuation('[') | Token::Punctuation('(') => depth += 1,
+            Token::Punctuation(']') | Token::Punctuation(')') => depth -= 1,
+            Token::Punctuation(',') if depth == 0 => {
+                operands.push(&tokens[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(&tokens[start..]);
+    operands
+}
+
+fn parse_operand(line: usize, tokens: &[Token]) -> Result<ParsedOperand, AssembleError> {
+    let mut tokens = tokens;
+    let mut modifier = None;
+    if let [Token::Identifier(word), rest @ ..] = tokens {
+        let parsed = match word.to_ascii_lowercase().as_str() {
+            "byte" => Some(Modifier::Byte),
+            "word" => Some(Modifier::Word),
+            "short" => Some(Modifier::Short),
+            "near" => Some(Modifier::Near),
+            "far" => Some(Modifier::Far),
+            _ => None,
+        };
+        if parsed.is_some() && !rest.is_empty() {
+            modifier = parsed;
+            tokens = rest;
+        }
+    }
+
+    let mut segment = None;
+    if let [Token::Identifier(word), Token::Punctuation(':'), rest @ ..] = tokens {
+        if let Some(register) = parse_register(word) {
+            if segment_register_number(&register).is_none() {
+                return Err(syntax_error(line, "expected a segment register"));
+            }
+            segment = Some(register);
+            tokens = rest;
+        }
+    }
+
+    let operand = match tokens {
+        [] => return Err(syntax_error(line, "expected an operand")),
+        [Token::Punctuation('['), inner @ .., Token::Punctuation(']')] => {
+            parse_memory(line, segment, inner)?
+        }
+        _ if segment.is_some() => return Err(syntax_error(line, "expected a memory operand")),
+        [Token::Identifier(word)] if parse_register(word).is_some() => {
+            Operand::Register(parse_register(word).unwrap())
+        }
+        _ => {
+            let colon = tokens
+                .iter()
+                .position(|token| *token == Token::Punctuation(':'));
+            match colon {
+                Some(colon) => Operand::FarPointer(
+                    parse_expression(line, &tokens[..colon])?,
+                    parse_expression(line, &tokens[colon + 1..])?,
+                ),
+                None => Operand::Immediate(parse_expression(line, tokens)?),
+            }
+        }
+    };
+
+    Ok(ParsedOperand { modifier, operand })
+}
+
+fn parse_memory(
+    line: usize,
+    segment: Option<RegisterName>,
+    tokens: &[Token],
+) -> Result<Operand, AssembleError> {
+    let mut registers = Vec::new();
+    let mut displacement: Option<Expression> = None;
+
+    let mut terms = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut negative = false;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punctuation('(') => depth += 1,
+            Token::Punctuation(')') => depth -= 1,
+            Token::Punctuation(sign @ ('+' | '-')) if depth == 0 => {
+                if index > start {
+                    terms.push((negative, &tokens[start..index]));
+                }
+                negative = *sign == '-';
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push((negative, &tokens[start..]));
+
+    for (negative, term) in terms {
+        if let [Token::Identifier(word)] = term {
+            if let Some(register) = parse_register(word) {
+                if negative {
+                    return Err(syntax_error(line, "registers cannot be subtracted"));
+                }
+                registers.push(register);
+                continue;
+            }
+        }
+        let mut expression = parse_expression(line, term)?;
+        if negative {
+            expression = Expression::Negate(Box::new(expression));
+        }
+        displacement = Some(match displacement {
+            Some(previous) => Expression::Binary('+', Box::new(previous), Box::new(expression)),
+            None => expression,
+        });
+    }
+
+    Ok(Operand::Memory {
+        segment,
+        registers,
+        displacement,
+    })
+}
+
+fn parse_expression(line: usize, tokens: &[Token]) -> Result<Expression, AssembleError> {
+    let mut position = 0;
+    let expression = parse_sum(line, tokens, &mut position)?;
+    if position != tokens.len() {
+        return Err(syntax_error(line, "unexpected tokens in expression"));
+    }
+    Ok(expression)
+}
+
+fn parse_sum(
+    line: usize,
+    tokens: &[Token],
+    position: &mut usize,
+) -> Result<Expression, AssembleError> {
+    let mut expression = parse_product(line, tokens, position)?;
+    while let Some(Token::Punctuation(operator @ ('+' | '-'))) = tokens.get(*position) {
+        *position += 1;
+        let right = parse_product(line, tokens, position)?;
+        expression = Expression::Binary(*operator, Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+fn parse_product(
+    line: usize,
+    tokens: &[Token],
+    position: &mut usize,
+) -> Result<Expression, AssembleError> {
+    let mut expression = parse_unary(line, tokens, position)?;
+    while let Some(Token::Punctuation(operator @ ('*' | '/'))) = tokens.get(*position) {
+        *position += 1;
+        let right = parse_unary(line, tokens, position)?;
+        expression = Expression::Binary(*operator, Box::new(expression), Box::new(right));
+    }
+    Ok(expression)
+}
+
+fn parse_unary(
+    line: usize,
+    tokens: &[Token],
+    position: &mut usize,
+) -> Result<Expression, AssembleError> {
+    let token = tokens
+        .get(*position)
+        .ok_or_else(|| syntax_error(line, "expected an expression"))?;
+    *position += 1;
+    match token {
+        Token::Punctuation('-') => Ok(Expression::Negate(Box::new(parse_unary(
+            line, tokens, position,
+        )?))),
+        Token::Punctuation('+') => parse_unary(line, tokens, position),
+        Token::Punctuation('(') => {
+            let expression = parse_sum(line, tokens, position)?;
+            match tokens.get(*position) {
+                Some(Token::Punctuation(')')) => {
+                    *position += 1;
+                    Ok(expression)
+                }
+                _ => Err(syntax_error(line, "expected `)`")),
+            }
+        }
+        Token::Number(value) => Ok(Expression::Number(*value)),
+        Token::String(bytes) if bytes.len() <= 2 => Ok(Expression::Number(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, byte| (value << 8) | i64::from(*byte)),
+        )),
+        Token::Identifier(word) if word == "$" => Ok(Expression::Here),
+        Token::Identifier(word) if parse_register(word).is_none() => {
+            Ok(Expression::Label(word.clone()))
+        }
+        _ => Err(syntax_error(line, "expected an expression")),
+    }
+}
+
+pub(crate) fn parse_register(word: &str) -> Option<RegisterName> {
+    match word.to_ascii_lowercase().as_str() {
+        "al" => Some(RegisterName::AL),
+        "cl" => Some(RegisterName::CL),
+        "dl" => Some(RegisterName::DL),
+        "bl" => Some(RegisterName::BL),
+        "ah" => Some(RegisterName::AH),
+        "ch" => Some(RegisterName::CH),
+        "dh" => Some(RegisterName::DH),
+        "bh" => Some(RegisterName::BH),
+        "ax" => Some(RegisterName::AX),
+        "cx" => Some(RegisterName::CX),
+        "dx" => Some(RegisterName::DX),
+        "bx" => Some(RegisterName::BX),
+        "sp" => Some(RegisterName::SP),
+        "bp" => Some(RegisterName::BP),
+        "si" => Some(RegisterName::SI),
+        "di" => Some(RegisterName::DI),
+        "es" => Some(RegisterName::ES),
+        "cs" => Some(RegisterName::CS),
+        "ss" => Some(RegisterName::SS),
+        "ds" => Some(RegisterName::DS),
+        _ => None,
+    }
+}
+
+fn parse_mnemonic(word: &str) -> Option<Mnemonic> {
+    let mnemonic = match word {
+        "aaa" => Mnemonic::AAA,
+        "aad" => Mnemonic::AAD,
+        "aam" => Mnemonic::AAM,
+        "aas" => Mnemonic::AAS,
+        "adc" => Mnemonic::ADC,
+        "add" => Mnemonic::ADD,
+        "and" => Mnemonic::AND,
+        "call" => Mnemonic::CALL,
+        "cbw" => Mnemonic::CBW,
+        "clc" => Mnemonic::CLC,
+        "cld" => Mnemonic::CLD,
+        "cli" => Mnemonic::CLI,
+        "cmc" => Mnemonic::CMC,
+        "cmp" => Mnemonic::CMP,
+        "cwd" => Mnemonic::CWD,
+        "daa" => Mnemonic::DAA,
+        "das" => Mnemonic::DAS,
+        "dec" => Mnemonic::DEC,
+        "div" => Mnemonic::DIV,
+        "hlt" => Mnemonic::HLT,
+        "idiv" => Mnemonic::IDIV,
+        "imul" => Mnemonic::IMUL,
+        "in" => Mnemonic::IN,
+        "inc" => Mnemonic::INC,
+        "int" => Mnemonic::INT,
+        "int3" => Mnemonic::INT3,
+        "into" => Mnemonic::INTO,
+        "iret" => Mnemonic::IRET,
+        "ja" | "jnbe" => Mnemonic::JA,
+        "jc" | "jb" | "jnae" => Mnemonic::JC,
+        "jcxz" => Mnemonic::JCXZ,
+        "jg" | "jnle" => Mnemonic::JG,
+        "jl" | "jnge" => Mnemonic::JL,
+        "jmp" => Mnemonic::JMP,
+        "jna" | "jbe" => Mnemonic::JNA,
+        "jnc" | "jnb" | "jae" => Mnemonic::JNC,
+        "jng" | "jle" => Mnemonic::JNG,
+        "jnl" | "jge" => Mnemonic::JNL,
+        "jno" => Mnemonic::JNO,
+        "jns" => Mnemonic::JNS,
+        "jnz" | "jne" => Mnemonic::JNZ,
+        "jo" => Mnemonic::JO,
+        "jpe" | "jp" => Mnemonic::JPE,
+        "jpo" | "jnp" => Mnemonic::JPO,
+        "js" => Mnemonic::JS,
+        "jz" | "je" => Mnemonic::JZ,
+        "lahf" => Mnemonic::LAHF,
+        "lds" => Mnemonic::LDS,
+        "lea" => Mnemonic::LEA,
+        "les" => Mnemonic::LES,
+        "loop" => Mnemonic::LOOP,
+        "loope" | "loopz" => Mnemonic::LOOPE,
+        "loopne" | "loopnz" => Mnemonic::LOOPNE,
+        "mov" => Mnemonic::MOV,
+        "mul" => Mnemonic::MUL,
+        "neg" => Mnemonic::NEG,
+        "nop" => Mnemonic::NOP,
+        "not" => Mnemonic::NOT,
+        "or" => Mnemonic::OR,
+        "out" => Mnemonic::OUT,
+        "pop" => Mnemonic::POP,
+        "popf" => Mnemonic::POPF,
+        "push" => Mnemonic::PUSH,
+        "pushf" => Mnemonic::PUSHF,
+        "rcl" => Mnemonic::RCL,
+        "rcr" => Mnemonic::RCR,
+        "ret" | "retn" => Mnemonic::RET,
+        "retf" => Mnemonic::RETF,
+        "rol" => Mnemonic::ROL,
+        "ror" => Mnemonic::ROR,
+        "sahf" => Mnemonic::SAHF,
+        "sar" => Mnemonic::SAR,
+        "sbb" => Mnemonic::SBB,
+        "shl" | "sal" => Mnemonic::SHL,
+        "shr" => Mnemonic::SHR,
+        "stc" => Mnemonic::STC,
+        "std" => Mnemonic::STD,
+        "sti" => Mnemonic::STI,
+        "sub" => Mnemonic::SUB,
+        "test" => Mnemonic::TEST,
+        "wait" | "fwait" => Mnemonic::WAIT,
+        "xchg" => Mnemonic::XCHG,
+        "xlat" | "xlatb" => Mnemonic::XLAT,
+        "xor" => Mnemonic::XOR,
+        _ => return None,
+    };
+    Some(mnemonic)
+}
+
+fn parse_string_mnemonic(word: &str) -> Option<(Mnemonic, bool)> {
+    let (mnemonic, word_operation) = match word {
+        "movsb" => (Mnemonic::MOVS, false),
+        "movsw" => (Mnemonic::MOVS, true),
+        "cmpsb" => (Mnemonic::CMPS, false),
+        "cmpsw" => (Mnemonic::CMPS, true),
+        "scasb" => (Mnemonic::SCAS, false),
+        "scasw" => (Mnemonic::SCAS, true),
+        "lodsb" => (Mnemonic::LODS, false),
+        "lodsw" => (Mnemonic::LODS, true),
+        "stosb" => (Mnemonic::STOS, false),
+        "stosw" => (Mnemonic::STOS, true),
+        _ => return None,
+    };
+    Some((mnemonic, word_operation))
+}
+
+fn evaluate(expression: &Expression, context: &Context) -> Result<i64, AssembleError> {
+    match expression {
+        Expression::Number(value) => Ok(*value),
+        Expression::Here => Ok(context.address),
+        Expression::Label(label) => match context.labels.get(label) {
+            Some(address) => Ok(*address),
+            None => Err(AssembleError::UndefinedLabel {
+                line: context.line,
+                label: label.clone(),
+            }),
+        },
+        Expression::Negate(inner) => Ok(evaluate(inner, context)?.wrapping_neg()),
+        Expression::Binary(operator, left, right) => {
+            let left = evaluate(left, context)?;
+            let right = evaluate(right, context)?;
+            match operator {
+                '+' => Ok(left.wrapping_add(right)),
+                '-' => Ok(left.wrapping_sub(right)),
+                '*' => Ok(left.wrapping_mul(right)),
+                _ => left
+                    .checked_div(right)
+                    .ok_or_else(|| syntax_error(context.line, "division by zero in expression")),
+            }
+        }
+    }
+}
+
+fn evaluate_word(expression: &Expression, context: &Context) -> Result<u16, AssembleError> {
+    let value = evaluate(expression, context)?;
+    if (-0x8000..=0xFFFF).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(AssembleError::Encode {
+            line: context.line,
+            error: EncodeError::ImmediateOutOfRange(value),
+        })
+    }
+}
+
+fn evaluate_immediate(
+    expression: &Expression,
+    word_operation: bool,
+    context: &Context,
+) -> Result<u16, AssembleError> {
+    let value = evaluate(expression, context)?;
+    let in_range = if word_operation {
+        (-0x8000..=0xFFFF).contains(&value)
+    } else {
+        (-0x80..=0xFF).contains(&value)
+    };
+    if !in_range {
+        return Err(AssembleError::Encode {
+            line: context.line,
+            error: EncodeError::ImmediateOutOfRange(value),
+        });
+    }
+    if word_operation {
+        Ok(value as u16)
+    } else {
+        Ok(u16::from(value as u8))
+    }
+}
+
+fn assemble_statement(
+    statement: &Statement,
+    context: &Context,
+    near_jump: bool,
+) -> Result<(Vec<u8>, bool), AssembleError> {
+    match statement {
+        Statement::Data { word, items } => {
+            let mut bytes = Vec::new();
+            for item in items {
+                match item {
+                    DataItem::Bytes(string) => {
+                        bytes.extend(string);
+                        if *word && string.len() % 2 == 1 {
+                            bytes.push(0);
+                        }
+                    }
+                    DataItem::Value(expression) => {
+                        let value =
+                            lenient(evaluate_immediate(expression, *word, context), context)?;
+                        if *word {
+                            bytes.extend(value.to_le_bytes());
+                        } else {
+                            bytes.push(value as u8);
+                        }
+                    }
+                }
+            }
+            Ok((bytes, false))
+        }
+        Statement::Origin(_) => Ok((Vec::new(), false)),
+        Statement::Instruction {
+            lock,
+            repeat,
+            segment_override,
+            mnemonic,
+            operands,
+        } => {
+            let mut instruction = Instruction {
+                length: 0,
+                instruction_category: InstructionCategory::NoOperands(Mnemonic::NOP),
+                lock: *lock,
+                repeat: *repeat,
+                segment_override: None,
+            };
+            let prefix_length = i64::from(u8::from(*lock))
+                + i64::from(u8::from(repeat.is_some()))
+                + i64::from(u8::from(segment_override.is_some()));
+
+            let mut memory_segment = None;
+            let (instruction_category, needs_near) = build_instruction(
+                mnemonic,
+                operands,
+                context,
+                prefix_length,
+                near_jump,
+                &mut memory_segment,
+            )?;
+            instruction.instruction_category = instruction_category;
+            instruction.segment_override = segment_override.or(memory_segment);
+
+            let bytes =
+                encode_instruction(&instruction).map_err(|error| AssembleError::Encode {
+                    line: context.line,
+                    error,
+                })?;
+            Ok((bytes, needs_near))
+        }
+    }
+}
+
+fn lenient<T: Default>(
+    result: Result<T, AssembleError>,
+    context: &Context,
+) -> Result<T, AssembleError> {
+    match result {
+        Err(_) if !context.strict => Ok(T::default()),
+        result => result,
+    }
+}
+
+fn build_instruction(
+    word: &str,
+    operands: &[ParsedOperand],
+    context: &Context,
+    prefix_length: i64,
+    near_jump: bool,
+    memory_segment: &mut Option<RegisterName>,
+) -> Result<(InstructionCategory, bool), AssembleError> {
+    let invalid = || AssembleError::Encode {
+        line: context.line,
+        error: EncodeError::InvalidOperands,
+    };
+
+    if let Some((mnemonic, word_operation)) = parse_string_mnemonic(word) {
+        if !operands.is_empty() {
+            return Err(invalid());
+        }
+        return Ok((
+            InstructionCategory::StringManipulation(mnemonic, word_operation),
+            false,
+        ));
+    }
+    if word == "esc" {
+        let [ParsedOperand {
+            operand: Operand::Immediate(opcode),
+            ..
+        }, operand] = operands
+        else {
+            return Err(invalid());
+        };
+        let opcode = lenient(evaluate(opcode, context), context)?;
+        let opcode = u8::try_from(opcode).map_err(|_| invalid())?;
+        let operand = register_memory(operand, context, memory_segment)?;
+        return Ok((InstructionCategory::Escape(opcode, operand), false));
+    }
+
+    let mnemonic = parse_mnemonic(word)
+        .ok_or_else(|| syntax_error(context.line, &format!("unknown instruction `{}`", word)))?;
+
+    let instruction_category = match (&mnemonic, operands) {
+        (_, []) => InstructionCategory::NoOperands(mnemonic),
+
+        (
+            Mnemonic::JA
+            | Mnemonic::JC
+            | Mnemonic::JCXZ
+            | Mnemonic::JG
+            | Mnemonic::JL
+            | Mnemonic::JNA
+            | Mnemonic::JNC
+            | Mnemonic::JNG
+            | Mnemonic::JNL
+            | Mnemonic::JNO
+            | Mnemonic::JNS
+            | Mnemonic::JNZ
+            | Mnemonic::JO
+            | Mnemonic::JPE
+            | Mnemonic::JPO
+            | Mnemonic::JS
+            | Mnemonic::JZ
+            | Mnemonic::LOOP
+            | Mnemonic::LOOPE
+            | Mnemonic::LOOPNE
+            | Mnemonic::JMP
+            | Mnemonic::CALL,
+            [ParsedOperand {
+                modifier,
+                operand: Operand::Immediate(target),
+            }],
+        ) => {
+            let target = lenient(evaluate(target, context), context)?;
+            let near = mnemonic == Mnemonic::CALL
+                || (mnemonic == Mnemonic::JMP
+                    && *modifier != Some(Modifier::Short)
+                    && (near_jump || *modifier == Some(Modifier::Near)));
+            if near {
+                let increment = target - (context.address + prefix_length + 3);
+                let increment = i16::try_from(increment)
+                    .or_else(|_| u16::try_from(increment).map(|increment| increment as i16))
+                    .or_else(|_| lenient(Err(jump_out_of_range(context, increment)), context))?;
+                return Ok((InstructionCategory::NearJump(mnemonic, increment), false));
+            }
+            let increment = target - (context.address + prefix_length + 2);
+            match i8::try_from(increment) {
+                Ok(increment) => InstructionCategory::Jump(mnemonic, increment),
+                Err(_) if mnemonic == Mnemonic::JMP && *modifier != Some(Modifier::Short) => {
+                    return Ok((InstructionCategory::Jump(mnemonic, 0), true));
+                }
+                Err(_) => {
+                    lenient::<()>(Err(jump_out_of_range(context, increment)), context)?;
+                    InstructionCategory::Jump(mnemonic, 0)
+                }
+            }
+        }
+        (
+            Mnemonic::JMP | Mnemonic::CALL,
+            [ParsedOperand {
+                operand: Operand::FarPointer(segment, offset),
+                ..
+            }],
+        ) => InstructionCategory::FarJump(
+            mnemonic,
+            lenient(evaluate_word(segment, context), context)?,
+            lenient(evaluate_word(offset, context), context)?,
+        ),
+        (
+            Mnemonic::JMP | Mnemonic::CALL,
+            [ParsedOperand {
+                modifier: Some(Modifier::Far),
+                operand,
+            }],
+        ) => InstructionCategory::IndirectFar(
+            mnemonic,
+            register_memory_operand(operand, context, memory_segment)?,
+        ),
+
+        (
+            Mnemonic::INT | Mnemonic::RET | Mnemonic::RETF | Mnemonic::AAM | Mnemonic::AAD,
+            [ParsedOperand {
+                operand: Operand::Immediate(immediate),
+                ..
+            }],
+        ) => {
+            let word_operation = matches!(mnemonic, Mnemonic::RET | Mnemonic::RETF);
+            let immediate = lenient(
+                evaluate_immediate(immediate, word_operation, context),
+                context,
+            )?;
+            InstructionCategory::Immediate(mnemonic, immediate)
+        }
+
+        (_, [operand]) => {
+            let word_operation = operand_width(operand).unwrap_or(matches!(
+                mnemonic,
+                Mnemonic::PUSH | Mnemonic::POP | Mnemonic::CALL | Mnemonic::JMP
+            ));
+            if operand_width(operand).is_none()
+                && !matches!(
+                    mnemonic,
+                    Mnemonic::PUSH | Mnemonic::POP | Mnemonic::CALL | Mnemonic::JMP
+                )
+            {
+                return Err(syntax_error(context.line, "operation size not specified"));
+            }
+            InstructionCategory::SingleOperand(
+                mnemonic,
+                register_memory(operand, context, memory_segment)?,
+                word_operation,
+            )
+        }
+
+        (
+            Mnemonic::IN,
+            [ParsedOperand {
+                operand: Operand::Register(register),
+                ..
+            }, port],
+        )
+        | (
+            Mnemonic::OUT,
+            [port, ParsedOperand {
+                operand: Operand::Register(register),
+                ..
+            }],
+        ) => {
+            let register = accumulator(accumulator_width(register).map_err(|_| invalid())? == 1);
+            match &port.operand {
+                Operand::Register(RegisterName::DX) => {
+                    InstructionCategory::VariablePort(mnemonic, register)
+                }
+                Operand::Immediate(port) => {
+                    let port = lenient(evaluate_immediate(port, false, context), context)?;
+                    InstructionCategory::FixedPort(mnemonic, port as u8, register)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        (
+            Mnemonic::ROL
+            | Mnemonic::ROR
+            | Mnemonic::RCL
+            | Mnemonic::RCR
+            | Mnemonic::SHL
+            | Mnemonic::SHR
+            | Mnemonic::SAR,
+            [operand, count],
+        ) => {
+            let by_cl = match &count.operand {
+                Operand::Register(RegisterName::CL) => true,
+                Operand::Immediate(count) if lenient(evaluate(count, context), context)? == 1 => {
+                    false
+                }
+                _ => return Err(invalid()),
+            };
+            let word_operation = operand_width(operand)
+                .ok_or_else(|| syntax_error(context.line, "operation size not specified"))?;
+            InstructionCategory::Shift(
+                mnemonic,
+                register_memory(operand, context, memory_segment)?,
+                word_operation,
+                by_cl,
+            )
+        }
+
+        (
+            _,
+            [dest, ParsedOperand {
+                modifier,
+                operand: Operand::Immediate(immediate),
+            }],
+        ) => {
+            let word_operation = operand_width(dest)
+                .or(match modifier {
+                    Some(Modifier::Byte) => Some(false),
+                    Some(Modifier::Word) => Some(true),
+                    _ => None,
+                })
+                .ok_or_else(|| syntax_error(context.line, "operation size not specified"))?;
+            let immediate = lenient(
+                evaluate_immediate(immediate, word_operation, context),
+                context,
+            )?;
+            match (&mnemonic, &dest.operand) {
+                (Mnemonic::MOV, Operand::Register(register)) => {
+                    InstructionCategory::ImmediateToRegister(mnemonic, immediate, *register)
+                }
+                _ => InstructionCategory::ImmediateToRegisterMemory(
+                    mnemonic,
+                    immediate,
+                    register_memory(dest, context, memory_segment)?,
+                    word_operation,
+                ),
+            }
+        }
+
+        (_, [dest, src]) => InstructionCategory::RegisterMemoryAndRegister(
+            mnemonic,
+            register_memory(src, context, memory_segment)?,
+            register_memory(dest, context, memory_segment)?,
+        ),
+
+        _ => return Err(invalid()),
+    };
+
+    Ok((instruction_category, false))
+}
+
+fn jump_out_of_range(context: &Context, increment: i64) -> AssembleError {
+    AssembleError::Encode {
+        line: context.line,
+        error: EncodeError::JumpOutOfRange(increment),
+    }
+}
+
+fn operand_width(operand: &ParsedOperand) -> Option<bool> {
+    match (&operand.operand, operand.modifier) {
+        (Operand::Register(register), _) => match register_number(register) {
+            Some((_, word_operation)) => Some(word_operation),
+            None => Some(true),
+        },
+        (_, Some(Modifier::Byte)) => Some(false),
+        (_, Some(Modifier::Word)) => Some(true),
+        _ => None,
+    }
+}
+
+fn register_memory(
+    operand: &ParsedOperand,
+    context: &Context,
+    memory_segment: &mut Option<RegisterName>,
+) -> Result<RegisterMemory, AssembleError> {
+    register_memory_operand(&operand.operand, context, memory_segment)
+}
+
+fn register_memory_operand(
+    operand: &Operand,
+    context: &Context,
+    memory_segment: &mut Option<RegisterName>,
+) -> Result<RegisterMemory, AssembleError> {
+    let invalid = || AssembleError::Encode {
+        line: context.line,
+        error: EncodeError::InvalidOperands,
+    };
+
+    let (segment, registers, displacement) = match operand {
+        Operand::Register(register) => return Ok(RegisterMemory::Register(*register)),
+        Operand::Memory {
+            segment,
+            registers,
+            displacement,
+        } => (segment, registers, displacement),
+        _ => return Err(invalid()),
+    };
+
+    if let Some(segment) = segment {
+        *memory_segment = Some(*segment);
+    }
+
+    let displacement = match displacement {
+        Some(displacement) => Some(lenient(evaluate_word(displacement, context), context)?),
+        None => None,
+    };
+
+    let mut base = None;
+    let mut index = None;
+    for register in registers {
+        match register {
+            RegisterName::BX | RegisterName::BP if base.is_none() => base = Some(*register),
+            RegisterName::SI | RegisterName::DI if index.is_none() => index = Some(*register),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let register_memory = match (base, index) {
+        (None, None) => RegisterMemory::DirectAddress(displacement.ok_or_else(invalid)?),
+        (Some(register), None) | (None, Some(register)) => match displacement {
+            None | Some(0) if register != RegisterName::BP => {
+                RegisterMemory::RegisterAddress(register)
+            }
+            displacement => {
+                let displacement = displacement.unwrap_or(0) as i16;
+                RegisterMemory::RegisterAddressDisplacement(register, displacement)
+            }
+        },
+        (Some(register), Some(offset_register)) => match displacement {
+            None | Some(0) => RegisterMemory::RegisterAddressOffset(register, offset_register),
+            Some(displacement) => RegisterMemory::RegisterAddressOffsetDisplacement(
+                register,
+                offset_register,
+                displacement as i16,
+            ),
+        },
+    };
+    Ok(register_memory)
+}
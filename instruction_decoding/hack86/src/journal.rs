@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+
+use crate::decode::RegisterName;
+use crate::prefetch::PrefetchQueue;
+use crate::simulate::Flags;
+
+// What one instruction changed, holding the values from before it ran so the
+// step can be undone.
+pub(crate) struct Entry {
+    pub(crate) registers: Vec<(RegisterName, u16)>,
+    pub(crate) ip: u16,
+    pub(crate) flags: Option<Flags>,
+    pub(crate) cycle_count: u32,
+    pub(crate) exit_code: Option<u8>,
+    pub(crate) halted: bool,
+    pub(crate) prefetch_queue: Option<PrefetchQueue>,
+    // Physical address and previous byte of every write, in write order.
+    pub(crate) memory: Vec<(usize, u8)>,
+}
+
+impl Entry {
+    // Roughly what the entry occupies on the heap, for the byte budget.
+    fn size(&self) -> usize {
+        std::mem::size_of::<Entry>()
+            + self.registers.len() * std::mem::size_of::<(RegisterName, u16)>()
+            + self.memory.len() * std::mem::size_of::<(usize, u8)>()
+    }
+}
+
+// Keeps the most recent `capacity` entries, further limited to about
+// `byte_budget` bytes in total since a single REP MOVSW can record 128 KiB of
+// writes. Older history is dropped.
+pub(crate) struct Journal {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    byte_budget: usize,
+    bytes: usize,
+}
+
+impl Journal {
+    pub(crate) fn new(capacity: usize, byte_budget: usize) -> Journal {
+        Journal {
+            entries: VecDeque::new(),
+            capacity,
+            byte_budget,
+            bytes: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, entry: Entry) {
+        self.bytes += entry.size();
+        self.entries.push_back(entry);
+        while self.entries.len() > self.capacity || self.bytes > self.byte_budget {
+            let Some(oldest) = self.entries.pop_front() else {
+                break;
+            };
+            self.bytes -= oldest.size();
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Entry> {
+        let entry = self.entries.pop_back()?;
+        self.bytes -= entry.size();
+        Some(entry)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
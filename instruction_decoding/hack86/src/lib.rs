@@ -0,0 +1,48 @@
+//! An 8086/8088 decoder, assembler and simulator.
+//!
+//! ```
+//! let instruction = hack86::decode_instruction(&[0x89, 0xd9]).unwrap();
+//! assert_eq!(instruction.to_string(), "mov cx, bx");
+//! assert_eq!(instruction.length(), 2);
+//! ```
+//!
+//! - [`decode`] turns machine code into [`Instruction`]s and formats them as
+//!   NASM source.
+//! - [`encode`] goes the other way, for single instructions or whole
+//!   programs.
+//! - [`simulate`] runs programs on a [`Hack86`], with [`memory`] holding the
+//!   1 MiB address space, [`dos`] providing program loading and DOS services
+//!   and [`trace`] reporting what each instruction did.
+//! - [`bus`] lets [`bus::Device`]s take over memory ranges and I/O ports;
+//!   [`devices`] has a text mode video buffer and a serial port.
+//! - [`debugger`] is the interactive front end used by `hack86 -i`.
+//!
+//! # Stability
+//!
+//! Everything reachable from this crate root is public API and follows
+//! semantic versioning: while the version is 0.x, breaking changes bump the
+//! minor version. Error enums and [`InstructionCategory`] are
+//! `#[non_exhaustive]`, so new variants are not breaking changes; match them
+//! with a wildcard arm. The exact text of trace output and of `Display`
+//! implementations may change in any release, except that instructions keep
+//! formatting as source NASM assembles back to the same instruction. The one
+//! exception is ESC, which NASM has no mnemonic for: it prints as
+//! `esc N, operand`, which only [`assemble`] accepts.
+
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod bus;
+pub mod debugger;
+pub mod decode;
+pub mod devices;
+pub mod dos;
+pub mod encode;
+mod journal;
+pub mod memory;
+mod prefetch;
+pub mod simulate;
+pub mod trace;
+
+pub use decode::{decode_instruction, DecodeError, Instruction, InstructionCategory};
+pub use encode::{assemble, encode_instruction};
+pub use simulate::{CpuModel, Hack86, CPU};
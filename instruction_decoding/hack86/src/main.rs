@@ -1,50 +1,240 @@
-mod decode;
-mod simulate;
+use std::io::Write;
+
+use hack86::{debugger, decode, devices, dos, encode, simulate, trace};
 
 fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
+    let mut args = std::env::args().collect::<Vec<String>>();
+    // Anything after `--` is passed to a DOS program as its command tail.
+    let program_args = match args.iter().position(|s| s == "--") {
+        Some(index) => args.split_off(index).split_off(1),
+        None => Vec::new(),
+    };
 
     let simulation_mode = args.iter().any(|s| s == "-s");
     let dump = args.iter().any(|s| s == "-d");
+    let assemble_mode = args.iter().any(|s| s == "-a");
+    let prefetch_model = args.iter().any(|s| s == "-p");
+    let interactive = args.iter().any(|s| s == "-i");
+    let trace_format = if args.iter().any(|s| s == "-jsonl") {
+        TraceFormat::JsonLines
+    } else if args.iter().any(|s| s == "-csv") {
+        TraceFormat::Csv
+    } else {
+        TraceFormat::Text
+    };
+    let model = if args.iter().any(|s| s == "-8088") {
+        simulate::CpuModel::Intel8088
+    } else {
+        simulate::CpuModel::Intel8086
+    };
 
     let path = args.iter().skip(1).find(|s| !s.starts_with("-"));
 
     if let Some(path) = path {
-        let instruction_stream = std::fs::read(&path).expect("failed to read file");
-        if simulation_mode {
-            simulate(instruction_stream, dump);
+        if assemble_mode {
+            assemble(path);
+            return;
+        }
+        let instruction_stream = std::fs::read(path).expect("failed to read file");
+        if simulation_mode || interactive {
+            let extension = std::path::Path::new(path)
+                .extension()
+                .map(|extension| extension.to_ascii_lowercase());
+            let command_tail = program_args
+                .iter()
+                .map(|arg| format!(" {}", arg))
+                .collect::<String>();
+            let computer = match extension.as_ref().and_then(|extension| extension.to_str()) {
+                Some("com") => {
+                    simulate::Hack86::load_com(&instruction_stream, command_tail.as_bytes(), model)
+                }
+                Some("exe") => simulate::Hack86::load_exe(
+                    &instruction_stream,
+                    command_tail.as_bytes(),
+                    simulate::EXE_LOAD_SEGMENT,
+                    model,
+                ),
+                _ => Ok(simulate::Hack86::new(instruction_stream, model)),
+            }
+            .unwrap_or_else(|error| {
+                eprintln!("{}: {}", path, error);
+                std::process::exit(1);
+            });
+            simulate(computer, prefetch_model, interactive, dump, trace_format);
         } else {
-            println!("bits 16");
-            println!("; {} disassembly:", &path);
-            decode_and_print(&instruction_stream);
+            decode_and_print(path, &instruction_stream)
+                .unwrap_or_else(|error| write_failed(&error));
         }
     } else {
         eprintln!("Please specify the path to a binary.");
     }
 }
 
-fn decode_and_print(instruction_stream: &[u8]) {
+fn decode_and_print(path: &str, instruction_stream: &[u8]) -> std::io::Result<()> {
+    let mut output = std::io::stdout().lock();
+    writeln!(output, "bits 16")?;
+    writeln!(output, "; {} disassembly:", path)?;
+
+    let mut instructions = Vec::new();
+    let mut decode_error = None;
     let mut instruction_index = 0;
     while instruction_index < instruction_stream.len() {
-        if let Ok(instruction) =
-            decode::decode_instruction(&instruction_stream[instruction_index..])
-        {
-            println!("{}", instruction.instruction_category);
-            instruction_index += usize::from(instruction.length);
-        } else {
-            panic!(
-                "unsupported instruction {:#010b} at offset {}",
-                instruction_stream[instruction_index], instruction_index
-            );
+        match decode::decode_instruction(&instruction_stream[instruction_index..]) {
+            Ok(instruction) => {
+                let length = usize::from(instruction.length());
+                instructions.push((instruction_index, instruction));
+                instruction_index += length;
+            }
+            Err(error) => {
+                decode_error = Some((instruction_index, error));
+                break;
+            }
+        }
+    }
+
+    let mut targets = instructions
+        .iter()
+        .filter_map(|(offset, instruction)| instruction.jump_target(*offset))
+        .filter(|target| {
+            *target == instruction_index || instructions.iter().any(|(offset, _)| offset == target)
+        })
+        .collect::<Vec<usize>>();
+    targets.sort_unstable();
+    targets.dedup();
+    let label = |target: usize| {
+        targets
+            .binary_search(&target)
+            .ok()
+            .map(|index| format!("label_{}", index))
+    };
+
+    for (offset, instruction) in &instructions {
+        if let Some(label) = label(*offset) {
+            writeln!(output, "{}:", label)?;
         }
+        let jump_label = instruction.jump_target(*offset).and_then(label);
+        writeln!(output, "{}", instruction.format(jump_label.as_deref()))?;
+    }
+    if let Some(label) = label(instruction_index) {
+        writeln!(output, "{}:", label)?;
     }
+
+    if let Some((offset, error)) = decode_error {
+        eprintln!(
+            "failed to decode instruction at offset {}: {}",
+            offset, error
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// Whoever was reading the output has gone away, as when it is piped into
+// `head`; stop quietly like any other command line tool.
+fn write_failed(error: &std::io::Error) -> ! {
+    if error.kind() == std::io::ErrorKind::BrokenPipe {
+        std::process::exit(0);
+    }
+    eprintln!("failed to write output: {}", error);
+    std::process::exit(1);
+}
+
+enum TraceFormat {
+    Text,
+    JsonLines,
+    Csv,
 }
 
-fn simulate(instructions: Vec<u8>, dump: bool) {
-    let mut computer = simulate::Hack86::new(instructions);
-    computer.simulate();
+fn simulate(
+    mut computer: simulate::Hack86,
+    prefetch_model: bool,
+    interactive: bool,
+    dump: bool,
+    trace_format: TraceFormat,
+) {
+    // Structured traces own stdout, so the program's own output goes to stderr.
+    let structured = match trace_format {
+        TraceFormat::Text => false,
+        TraceFormat::JsonLines => {
+            computer.set_trace_sink(Box::new(trace::JsonLinesTrace::new(std::io::stdout())));
+            true
+        }
+        TraceFormat::Csv => {
+            computer.set_trace_sink(Box::new(trace::CsvTrace::new(std::io::stdout())));
+            true
+        }
+    };
+    let program_output = || -> Box<dyn std::io::Write> {
+        if structured {
+            Box::new(std::io::stderr())
+        } else {
+            Box::new(std::io::stdout())
+        }
+    };
+    computer.set_interrupt_handler(Box::new(dos::DosServices::new(program_output())));
+    computer.attach_device(Box::new(devices::TextVideo::new()));
+    computer.attach_device(Box::new(devices::SerialPort::new(
+        devices::COM1,
+        program_output(),
+    )));
+    if prefetch_model {
+        computer.enable_prefetch_model();
+    }
+    if interactive {
+        let mut debugger = debugger::Debugger::new(computer);
+        debugger.run();
+        computer = debugger.into_computer();
+    } else {
+        match computer.simulate() {
+            Ok(()) => {}
+            Err(
+                simulate::SimulationError::Trace(error) | simulate::SimulationError::Output(error),
+            ) => write_failed(&error),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(error) = computer
+        .device::<devices::SerialPort<Box<dyn std::io::Write>>>()
+        .and_then(|port| port.error())
+    {
+        write_failed(error);
+    }
+    if let Some(video) = computer.device::<devices::TextVideo>() {
+        let screen = video.text();
+        if !screen.is_empty() {
+            writeln!(program_output(), "\nScreen:\n{}", screen)
+                .unwrap_or_else(|error| write_failed(&error));
+        }
+    }
     if dump {
-        let memory = computer.memory();
+        // The first 64 KiB, which is what the course's tools expect to load.
+        let memory = &computer.memory()[..0x10000];
         std::fs::write("hack86_memory.data", memory).expect("Failed to write memory to file");
     }
+    if let Some(exit_code) = computer.exit_code() {
+        std::process::exit(i32::from(exit_code));
+    }
+}
+
+fn assemble(path: &str) {
+    // The binary takes the source's name without its extension, which would be
+    // the source itself when there is no extension.
+    let source_path = std::path::Path::new(path);
+    if source_path.extension().is_none() {
+        eprintln!("{}: source file needs an extension such as .asm", path);
+        std::process::exit(1);
+    }
+    let source = std::fs::read_to_string(path).expect("failed to read file");
+    match encode::assemble(&source) {
+        Ok(bytes) => {
+            std::fs::write(source_path.with_extension(""), bytes).expect("failed to write binary");
+        }
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,106 @@
+use crate::bus::Bus;
+
+pub const MEMORY_SIZE: usize = 0x100000;
+
+// The 8086 address space as the EU sees it: offsets wrap within their 64 KiB
+// segment and physical addresses wrap at 1 MiB. Addresses claimed by a device
+// on the bus go to the device instead of RAM.
+pub struct Memory {
+    bytes: Vec<u8>,
+    bus: Bus,
+    // Previous contents of every byte written while recording, for the
+    // simulator's undo journal.
+    writes: Option<Vec<(usize, u8)>>,
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory {
+            bytes: vec![0; MEMORY_SIZE],
+            bus: Bus::new(),
+            writes: None,
+        }
+    }
+
+    // RAM only; device memory isn't included.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn read(&self, segment: u16, offset: u16, word_operation: bool) -> u16 {
+        let low = self.byte(physical_address(segment, offset));
+        if word_operation {
+            let high = self.byte(physical_address(segment, offset.wrapping_add(1)));
+            u16::from_le_bytes([low, high])
+        } else {
+            u16::from(low)
+        }
+    }
+
+    pub fn write(&mut self, segment: u16, offset: u16, value: u16, word_operation: bool) {
+        let bytes = value.to_le_bytes();
+        self.store(physical_address(segment, offset), bytes[0]);
+        if word_operation {
+            self.store(physical_address(segment, offset.wrapping_add(1)), bytes[1]);
+        }
+    }
+
+    pub fn load(&mut self, segment: u16, offset: u16, bytes: &[u8]) {
+        for (index, byte) in bytes.iter().enumerate() {
+            let offset = offset.wrapping_add(index as u16);
+            self.store(physical_address(segment, offset), *byte);
+        }
+    }
+
+    pub(crate) fn record_writes(&mut self) {
+        self.writes = Some(Vec::new());
+    }
+
+    pub(crate) fn take_writes(&mut self) -> Vec<(usize, u8)> {
+        self.writes.take().unwrap_or_default()
+    }
+
+    pub(crate) fn restore(&mut self, address: usize, byte: u8) {
+        if !self.bus.write(address, byte) {
+            self.bytes[address] = byte;
+        }
+    }
+
+    fn byte(&self, address: usize) -> u8 {
+        self.bus.read(address).unwrap_or(self.bytes[address])
+    }
+
+    fn store(&mut self, address: usize, byte: u8) {
+        if self.writes.is_some() {
+            let previous = self.byte(address);
+            if let Some(writes) = &mut self.writes {
+                writes.push((address, previous));
+            }
+        }
+        self.restore(address, byte);
+    }
+
+    pub fn fetch(&self, segment: u16, offset: u16, length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|index| self.byte(physical_address(segment, offset.wrapping_add(index as u16))))
+            .collect()
+    }
+}
+
+pub fn physical_address(segment: u16, offset: u16) -> usize {
+    ((usize::from(segment) << 4) + usize::from(offset)) % MEMORY_SIZE
+}
@@ -0,0 +1,106 @@
+use crate::simulate::CpuModel;
+
+const BUS_CYCLE_CLOCKS: u64 = 4;
+
+// Approximates the bus interface unit: the execution unit consumes
+// instruction bytes from the prefetch queue, and whenever the bus is not busy
+// with one of its memory transfers the BIU fills the queue one bus cycle at a
+// time. A taken jump flushes the queue.
+#[derive(Clone)]
+pub(crate) struct PrefetchQueue {
+    model: CpuModel,
+    queued_bytes: u16,
+    fetch_address: u16,
+    bus_free_at: u64,
+    clock: u64,
+}
+
+impl PrefetchQueue {
+    pub(crate) fn new(model: CpuModel, ip: u16) -> PrefetchQueue {
+        PrefetchQueue {
+            model,
+            queued_bytes: 0,
+            fetch_address: ip,
+            bus_free_at: 0,
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    // Returns the clocks that elapsed while running one instruction whose
+    // execution unit time (from the clock table) is `execution_clocks`, of
+    // which `bus_cycles` bus cycles are spent on its own memory transfers.
+    pub(crate) fn execute(
+        &mut self,
+        length: u16,
+        execution_clocks: u32,
+        bus_cycles: u32,
+        next_ip: u16,
+        sequential: bool,
+    ) -> u64 {
+        let start = self.clock;
+
+        let mut needed = length;
+        loop {
+            let consumed = needed.min(self.queued_bytes);
+            self.queued_bytes -= consumed;
+            needed -= consumed;
+            if needed == 0 {
+                break;
+            }
+            self.fetch(self.clock);
+            self.clock = self.clock.max(self.bus_free_at);
+        }
+
+        if bus_cycles > 0 {
+            // The EU has to wait for a prefetch that is already on the bus.
+            self.clock = self.clock.max(self.bus_free_at);
+            self.bus_free_at = self.clock + u64::from(bus_cycles) * BUS_CYCLE_CLOCKS;
+        }
+
+        let execution_start = self.clock;
+        let execution_end = execution_start + u64::from(execution_clocks);
+        while self.room() >= self.fetch_width()
+            && self.bus_free_at.max(execution_start) < execution_end
+        {
+            self.fetch(execution_start);
+        }
+        self.clock = execution_end;
+
+        if !sequential {
+            self.queued_bytes = 0;
+            self.fetch_address = next_ip;
+        }
+
+        self.clock - start
+    }
+
+    fn queue_size(&self) -> u16 {
+        match self.model {
+            CpuModel::Intel8086 => 6,
+            CpuModel::Intel8088 => 4,
+        }
+    }
+
+    fn room(&self) -> u16 {
+        self.queue_size() - self.queued_bytes
+    }
+
+    fn fetch_width(&self) -> u16 {
+        match self.model {
+            CpuModel::Intel8086 if self.fetch_address.is_multiple_of(2) => 2,
+            _ => 1,
+        }
+    }
+
+    fn fetch(&mut self, not_before: u64) {
+        let width = self.fetch_width();
+        let start = self.bus_free_at.max(not_before);
+        self.bus_free_at = start + BUS_CYCLE_CLOCKS;
+        self.queued_bytes = (self.queued_bytes + width).min(self.queue_size());
+        self.fetch_address = self.fetch_address.wrapping_add(width);
+    }
+}
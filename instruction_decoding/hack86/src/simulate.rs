@@ -1,32 +1,237 @@
-use crate::decode::{self, Instruction, RegisterMemory, RegisterName};
+use crate::bus::Device;
+use crate::decode::{
+    self, DecodeError, Instruction, Mnemonic, RegisterMemory, RegisterName, Repeat,
+};
+use crate::dos::ExeHeader;
+use crate::journal::{self, Journal};
+use crate::memory::{physical_address, Memory};
+use crate::prefetch::PrefetchQueue;
+use crate::trace::{
+    MemoryWrite, PrefetchClocks, RegisterChange, TextTrace, Trace, TraceClocks, TraceSink,
+};
 
-pub struct Register(RegisterName, u16);
+#[derive(Clone)]
+pub struct Register(u16);
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Flags {
-    sf: bool,
+    cf: bool,
+    pf: bool,
+    af: bool,
     zf: bool,
+    sf: bool,
+    tf: bool,
+    if_: bool,
+    df: bool,
+    of: bool,
 }
 
 impl std::fmt::Display for Flags {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut s = String::new();
-        if self.sf {
-            s.push('S');
+        let flags = [
+            (self.cf, 'C'),
+            (self.pf, 'P'),
+            (self.af, 'A'),
+            (self.zf, 'Z'),
+            (self.sf, 'S'),
+            (self.tf, 'T'),
+            (self.if_, 'I'),
+            (self.df, 'D'),
+            (self.of, 'O'),
+        ];
+        let s = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name)
+            .collect::<String>();
+        write!(f, "{}", s)
+    }
+}
+
+// PUSHF stores the flags in their hardware bit positions; the 8086 always
+// reads back bit 1 and the top four bits as set.
+impl From<&Flags> for u16 {
+    fn from(flags: &Flags) -> u16 {
+        [
+            (flags.cf, 0),
+            (flags.pf, 2),
+            (flags.af, 4),
+            (flags.zf, 6),
+            (flags.sf, 7),
+            (flags.tf, 8),
+            (flags.if_, 9),
+            (flags.df, 10),
+            (flags.of, 11),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0xF002, |word, (_, bit)| word | (1 << bit))
+    }
+}
+
+impl From<u16> for Flags {
+    fn from(word: u16) -> Flags {
+        let bit = |n: u16| (word & (1 << n)) != 0;
+        Flags {
+            cf: bit(0),
+            pf: bit(2),
+            af: bit(4),
+            zf: bit(6),
+            sf: bit(7),
+            tf: bit(8),
+            if_: bit(9),
+            df: bit(10),
+            of: bit(11),
         }
-        if self.zf {
-            s.push('Z');
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SimulationError {
+    Decode { ip: u16, error: DecodeError },
+    // Decoded fine, but the simulator has no implementation for it.
+    Unsupported(Instruction),
+    // Neither the host handler nor the vector table handles the interrupt.
+    UnhandledInterrupt(u8),
+    // The trace sink failed to write, for example to a closed pipe.
+    Trace(std::io::Error),
+    // An interrupt handler failed to write the program's output.
+    Output(std::io::Error),
+}
+
+impl std::fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationError::Decode { ip, error } => {
+                write!(f, "failed to decode instruction at ip {:#x}: {}", ip, error)
+            }
+            SimulationError::Unsupported(instruction) => {
+                write!(f, "cannot simulate `{}`", instruction)
+            }
+            SimulationError::UnhandledInterrupt(vector) => {
+                write!(f, "no handler for interrupt {:#04x}", vector)
+            }
+            SimulationError::Trace(error) => write!(f, "failed to write trace: {}", error),
+            SimulationError::Output(error) => {
+                write!(f, "failed to write program output: {}", error)
+            }
         }
-        write!(f, "{}", s)
     }
 }
 
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LoadError {
+    ComTooLarge(usize),
+    InvalidExeHeader,
+    TruncatedExe { expected: usize, actual: usize },
+    ExeTooLarge,
+    InvalidLoadSegment(u16),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::ComTooLarge(size) => {
+                write!(
+                    f,
+                    ".COM image of {} bytes does not fit in one segment",
+                    size
+                )
+            }
+            LoadError::InvalidExeHeader => write!(f, "not a valid MZ executable header"),
+            LoadError::TruncatedExe { expected, actual } => write!(
+                f,
+                "executable should be {} bytes long but is only {}",
+                expected, actual
+            ),
+            LoadError::ExeTooLarge => write!(f, "executable does not fit in conventional memory"),
+            LoadError::InvalidLoadSegment(segment) => {
+                write!(f, "cannot load an executable at segment {:#x}", segment)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SeekError {
+    HistoryUnavailable(u64),
+    ProgramEnded(u64),
+    Simulation(SimulationError),
+}
+
+impl std::fmt::Display for SeekError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeekError::HistoryUnavailable(count) => {
+                write!(f, "no recorded history before instruction {}", count)
+            }
+            SeekError::ProgramEnded(count) => {
+                write!(f, "program ended after {} instructions", count)
+            }
+            SeekError::Simulation(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+pub enum InterruptResult {
+    Unhandled,
+    Handled,
+    Exit(u8),
+    // The service failed to write the program's output; the simulation stops
+    // with `SimulationError::Output`.
+    OutputFailed(std::io::Error),
+}
+
+// Lets the host service software interrupts natively. An unhandled interrupt
+// is dispatched through the vector table at address 0 as usual, and is an
+// error when its vector is still 0:0.
+pub trait InterruptHandler {
+    fn interrupt(&mut self, vector: u8, cpu: &mut CPU, memory: &mut Memory) -> InterruptResult;
+}
+
 pub struct Hack86 {
     cpu: CPU,
-    instructions: Vec<u8>,
-    memory: [u8; 65536],
+    memory: Memory,
+    // Physical address just past the loaded program. Running on from its last
+    // instruction ends the simulation, as the course listings expect, but a
+    // jump can still take execution past it, to code the program wrote there.
+    code_end: usize,
+    ran_off_end: bool,
+    interrupt_handler: Option<Box<dyn InterruptHandler>>,
+    instruction_count: u64,
+    journal: Option<Journal>,
+    trace_sink: Box<dyn TraceSink>,
 }
 
+const WORD_REGISTERS: [RegisterName; 12] = [
+    RegisterName::AX,
+    RegisterName::BX,
+    RegisterName::CX,
+    RegisterName::DX,
+    RegisterName::SP,
+    RegisterName::BP,
+    RegisterName::SI,
+    RegisterName::DI,
+    RegisterName::ES,
+    RegisterName::CS,
+    RegisterName::SS,
+    RegisterName::DS,
+];
+
+const PSP_SEGMENT: u16 = 0x1000;
+pub const EXE_LOAD_SEGMENT: u16 = PSP_SEGMENT + 0x10;
+const COM_MAX_SIZE: usize = 0xFF00;
+const PSP_SIZE: u16 = 0x100;
+
+enum Location {
+    Register(RegisterName),
+    Memory { segment: u16, offset: u16 },
+}
+
+#[derive(Clone)]
 pub struct CPU {
     ax: Register,
     cx: Register,
@@ -37,380 +242,1528 @@ pub struct CPU {
     si: Register,
     di: Register,
 
+    es: Register,
+    cs: Register,
+    ss: Register,
+    ds: Register,
+
     ip: u16,
 
     flags: Flags,
 
     cycle_count: u32,
+
+    model: CpuModel,
+
+    prefetch_queue: Option<PrefetchQueue>,
+
+    exit_code: Option<u8>,
+
+    // Set by HLT. Nothing raises hardware interrupts, so it never resumes.
+    halted: bool,
+
+    // What the instruction being executed has done so far.
+    trace: Trace,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuModel {
+    Intel8086,
+    Intel8088,
 }
 
 impl Hack86 {
-    pub fn new(instructions: Vec<u8>) -> Hack86 {
+    pub fn new(program: Vec<u8>, model: CpuModel) -> Hack86 {
+        let mut memory = Memory::new();
+        memory.load(0, 0, &program);
+        Hack86::with_memory(CPU::new(model), memory, program.len())
+    }
+
+    fn with_memory(cpu: CPU, memory: Memory, code_end: usize) -> Hack86 {
         Hack86 {
-            cpu: CPU::new(),
-            instructions,
-            memory: [0; 65536],
+            ran_off_end: physical_address(cpu.cs.0, cpu.ip) == code_end,
+            cpu,
+            memory,
+            code_end,
+            interrupt_handler: None,
+            instruction_count: 0,
+            journal: None,
+            trace_sink: Box::new(TextTrace::new(std::io::stdout())),
         }
     }
 
-    pub fn simulate(&mut self) {
-        while usize::from(self.cpu.ip) < self.instructions.len() {
-            if let Ok(instruction) =
-                decode::decode_instruction(&self.instructions[usize::from(self.cpu.ip)..])
-            {
-                self.cpu.ip += u16::from(instruction.length);
-                self.cpu.execute(&instruction, &mut self.memory);
-            } else {
-                panic!(
-                    "unsupported instruction {:#010b} at offset {}",
-                    self.instructions[usize::from(self.cpu.ip)],
-                    self.cpu.ip
-                );
+    // Lays out a DOS .COM program the way COMMAND.COM would: a program segment
+    // prefix at offset 0 holding the command tail, the image at 0100h and
+    // all segment registers pointing at the PSP.
+    pub fn load_com(
+        image: &[u8],
+        command_tail: &[u8],
+        model: CpuModel,
+    ) -> Result<Hack86, LoadError> {
+        if image.len() > COM_MAX_SIZE {
+            return Err(LoadError::ComTooLarge(image.len()));
+        }
+
+        let mut memory = Memory::new();
+        write_psp(&mut memory, PSP_SEGMENT, 0xA000, command_tail);
+        memory.load(PSP_SEGMENT, PSP_SIZE, image);
+
+        let mut cpu = CPU::new(model);
+        cpu.cs.0 = PSP_SEGMENT;
+        cpu.ds.0 = PSP_SEGMENT;
+        cpu.es.0 = PSP_SEGMENT;
+        cpu.ss.0 = PSP_SEGMENT;
+        // The zero word already at the top of the stack makes a final RET
+        // land on the INT 20h at the start of the PSP.
+        cpu.sp.0 = 0xFFFE;
+        cpu.ip = PSP_SIZE;
+
+        let code_end = physical_address(PSP_SEGMENT, PSP_SIZE) + image.len();
+        Ok(Hack86::with_memory(cpu, memory, code_end))
+    }
+
+    // Places the load module of an MZ executable at `load_segment`, with the
+    // PSP in the 256 bytes just below it, and fixes up every relocated
+    // segment word by the load segment.
+    pub fn load_exe(
+        image: &[u8],
+        command_tail: &[u8],
+        load_segment: u16,
+        model: CpuModel,
+    ) -> Result<Hack86, LoadError> {
+        let header = ExeHeader::parse(image)?;
+        if load_segment < 0x10 {
+            return Err(LoadError::InvalidLoadSegment(load_segment));
+        }
+        let psp_segment = load_segment - 0x10;
+
+        let module = &image[header.header_size..header.header_size + header.load_module_size];
+        let start = physical_address(load_segment, 0);
+        let minimum_end = start + module.len() + usize::from(header.min_alloc) * 16;
+        if minimum_end > 0xA0000 {
+            return Err(LoadError::ExeTooLarge);
+        }
+        let module_paragraphs = module.len().div_ceil(16) as u32;
+        let end_segment =
+            (u32::from(load_segment) + module_paragraphs + u32::from(header.max_alloc)).min(0xA000)
+                as u16;
+
+        let mut memory = Memory::new();
+        write_psp(&mut memory, psp_segment, end_segment, command_tail);
+        for (index, chunk) in module.chunks(0x10000).enumerate() {
+            memory.load(load_segment.wrapping_add(index as u16 * 0x1000), 0, chunk);
+        }
+        for &(offset, segment) in &header.relocations {
+            let segment = load_segment.wrapping_add(segment);
+            let value = memory
+                .read(segment, offset, true)
+                .wrapping_add(load_segment);
+            memory.write(segment, offset, value, true);
+        }
+
+        let mut cpu = CPU::new(model);
+        cpu.cs.0 = load_segment.wrapping_add(header.cs);
+        cpu.ip = header.ip;
+        cpu.ss.0 = load_segment.wrapping_add(header.ss);
+        cpu.sp.0 = header.sp;
+        cpu.ds.0 = psp_segment;
+        cpu.es.0 = psp_segment;
+
+        Ok(Hack86::with_memory(cpu, memory, start + module.len()))
+    }
+
+    pub fn simulate(&mut self) -> Result<(), SimulationError> {
+        while self.running() {
+            self.step()?;
+        }
+
+        self.trace_sink
+            .finish(&self.cpu)
+            .map_err(SimulationError::Trace)
+    }
+
+    pub fn running(&self) -> bool {
+        self.cpu.exit_code.is_none() && !self.cpu.halted && !self.ran_off_end
+    }
+
+    pub fn step(&mut self) -> Result<(), SimulationError> {
+        let instruction = self.decode_at(self.cpu.cs.0, self.cpu.ip)?;
+        let before = self.journal.as_ref().map(|_| {
+            self.memory.record_writes();
+            self.cpu.clone()
+        });
+
+        let (cs, ip, cycle_count) = (self.cpu.cs.0, self.cpu.ip, self.cpu.cycle_count);
+        self.cpu.ip = self.cpu.ip.wrapping_add(u16::from(instruction.length));
+        let trace = match self.cpu.execute(
+            &instruction,
+            &mut self.memory,
+            self.interrupt_handler
+                .as_mut()
+                .map(|handler| handler.as_mut() as &mut dyn InterruptHandler),
+        ) {
+            Ok(trace) => trace,
+            Err(error) => {
+                // Leave the CPU at the instruction it could not run.
+                self.cpu.ip = ip;
+                self.cpu.cycle_count = cycle_count;
+                self.memory.take_writes();
+                return Err(error);
+            }
+        };
+        self.instruction_count += 1;
+        let next_ip = ip.wrapping_add(u16::from(instruction.length));
+        self.ran_off_end = !unconditional_jump(&instruction)
+            && self.cpu.cs.0 == cs
+            && self.cpu.ip == next_ip
+            && physical_address(cs, next_ip) == self.code_end;
+
+        if let (Some(journal), Some(before)) = (&mut self.journal, before) {
+            let registers = WORD_REGISTERS
+                .iter()
+                .filter(|name| before.get_register(name) != self.cpu.get_register(name))
+                .map(|name| (*name, before.get_register(name)))
+                .collect();
+            journal.push(journal::Entry {
+                registers,
+                ip: before.ip,
+                flags: (before.flags != self.cpu.flags).then_some(before.flags),
+                cycle_count: before.cycle_count,
+                exit_code: before.exit_code,
+                halted: before.halted,
+                prefetch_queue: before.prefetch_queue,
+                memory: self.memory.take_writes(),
+            });
+        }
+        // The instruction has run either way; a failed trace just stops the
+        // simulation after it.
+        self.trace_sink
+            .instruction(&trace)
+            .map_err(SimulationError::Trace)
+    }
+
+    // Keeps enough history to undo up to `capacity` of the most recent
+    // instructions, in no more than about `byte_budget` bytes.
+    pub fn enable_journal(&mut self, capacity: usize, byte_budget: usize) {
+        self.journal = Some(Journal::new(capacity, byte_budget));
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    // Undoes the last instruction. Returns false when there is no recorded
+    // history left to undo. Registers and memory, device memory included, go
+    // back to how they were; port I/O is not journaled, so device registers
+    // set through OUT and output a device has already sent stay as they are.
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.journal.as_mut().and_then(|journal| journal.pop()) else {
+            return false;
+        };
+
+        for (address, byte) in entry.memory.into_iter().rev() {
+            self.memory.restore(address, byte);
+        }
+        for (name, value) in entry.registers {
+            self.cpu.register_mut(&name).0 = value;
+        }
+        self.cpu.ip = entry.ip;
+        if let Some(flags) = entry.flags {
+            self.cpu.flags = flags;
+        }
+        self.cpu.cycle_count = entry.cycle_count;
+        self.cpu.exit_code = entry.exit_code;
+        self.cpu.halted = entry.halted;
+        self.cpu.prefetch_queue = entry.prefetch_queue;
+        self.ran_off_end = false;
+        self.instruction_count -= 1;
+        true
+    }
+
+    // Moves to the state after `instruction_count` instructions, undoing
+    // recorded steps to go back and simulating to go forward.
+    pub fn seek(&mut self, instruction_count: u64) -> Result<(), SeekError> {
+        while self.instruction_count > instruction_count {
+            if !self.step_back() {
+                return Err(SeekError::HistoryUnavailable(self.instruction_count));
+            }
+        }
+        while self.instruction_count < instruction_count {
+            if !self.running() {
+                return Err(SeekError::ProgramEnded(self.instruction_count));
             }
+            self.step().map_err(SeekError::Simulation)?;
         }
+        Ok(())
+    }
 
-        println!();
-        println!("Final registers:");
-        println!("{}", self.cpu);
+    pub fn journal_length(&self) -> usize {
+        self.journal.as_ref().map_or(0, |journal| journal.len())
+    }
+
+    // Instructions are decoded straight out of memory, so they see earlier
+    // stores. Nearly all fit in the first few bytes; the window only grows
+    // for long runs of prefixes.
+    pub(crate) fn decode_at(
+        &self,
+        segment: u16,
+        offset: u16,
+    ) -> Result<Instruction, SimulationError> {
+        let mut length = 16;
+        loop {
+            let bytes = self.memory.fetch(segment, offset, length);
+            match decode::decode_instruction(&bytes) {
+                Err(DecodeError::Truncated { .. }) if length < 0x10000 => length *= 2,
+                result => {
+                    return result.map_err(|error| SimulationError::Decode { ip: offset, error })
+                }
+            }
+        }
+    }
+
+    pub fn enable_prefetch_model(&mut self) {
+        self.cpu.prefetch_queue = Some(PrefetchQueue::new(self.cpu.model, self.cpu.ip));
+    }
+
+    pub fn attach_device(&mut self, device: Box<dyn Device>) {
+        self.memory.bus_mut().attach(device);
+    }
+
+    pub fn device<T: Device>(&self) -> Option<&T> {
+        self.memory.bus().device()
+    }
+
+    pub fn set_trace_sink(&mut self, trace_sink: Box<dyn TraceSink>) {
+        self.trace_sink = trace_sink;
+    }
+
+    pub fn set_interrupt_handler(&mut self, interrupt_handler: Box<dyn InterruptHandler>) {
+        self.interrupt_handler = Some(interrupt_handler);
     }
 
     pub fn memory(&self) -> &[u8] {
-        &self.memory
+        self.memory.bytes()
+    }
+
+    // Unlike `memory`, this sees device memory too.
+    pub fn read_memory(&self, segment: u16, offset: u16, length: usize) -> Vec<u8> {
+        self.memory.fetch(segment, offset, length)
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn exit_code(&self) -> Option<u8> {
+        self.cpu.exit_code
     }
 }
 
 impl CPU {
-    pub fn new() -> CPU {
+    pub fn new(model: CpuModel) -> CPU {
         CPU {
-            ax: Register(RegisterName::AX, 0),
-            bx: Register(RegisterName::BX, 0),
-            cx: Register(RegisterName::CX, 0),
-            dx: Register(RegisterName::DX, 0),
-            bp: Register(RegisterName::BP, 0),
-            sp: Register(RegisterName::SP, 0),
-            di: Register(RegisterName::DI, 0),
-            si: Register(RegisterName::SI, 0),
+            model,
+            prefetch_queue: None,
+
+            ax: Register(0),
+            bx: Register(0),
+            cx: Register(0),
+            dx: Register(0),
+            bp: Register(0),
+            sp: Register(0),
+            di: Register(0),
+            si: Register(0),
+
+            es: Register(0),
+            cs: Register(0),
+            ss: Register(0),
+            ds: Register(0),
 
             ip: 0,
 
-            flags: Flags {
-                sf: false,
-                zf: false,
-            },
+            flags: Flags::default(),
 
             cycle_count: 0,
+
+            exit_code: None,
+
+            halted: false,
+
+            trace: Trace::default(),
         }
     }
 
-    pub fn execute(&mut self, instruction: &Instruction, memory: &mut [u8; 65536]) {
-        print!("{} ;", instruction.instruction_category);
+    pub fn ip(&self) -> u16 {
+        self.ip
+    }
 
-        let clocks = instruction.clocks();
-        self.cycle_count += u32::from(clocks.0 + clocks.1);
-        if clocks.1 > 0 {
-            print!(
-                " Clocks: +{} = {} ({} + {}ea) |",
-                (clocks.0 + clocks.1),
-                self.cycle_count,
-                clocks.0,
-                clocks.1
-            );
-        } else {
-            print!(
-                " Clocks: +{} = {} |",
-                (clocks.0 + clocks.1),
-                self.cycle_count
-            );
-        }
+    pub fn set_ip(&mut self, ip: u16) {
+        self.ip = ip;
+    }
+
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
 
-        let original_flags = self.flags.clone();
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn prefetch_clocks(&self) -> Option<u64> {
+        self.prefetch_queue
+            .as_ref()
+            .map(|prefetch_queue| prefetch_queue.clock())
+    }
+
+    pub fn execute(
+        &mut self,
+        instruction: &Instruction,
+        memory: &mut Memory,
+        interrupt_handler: Option<&mut dyn InterruptHandler>,
+    ) -> Result<Trace, SimulationError> {
+        let unsupported = || SimulationError::Unsupported(instruction.clone());
+        let original_ip = self.ip.wrapping_sub(u16::from(instruction.length));
+        self.trace = Trace {
+            cs: self.cs.0,
+            ip: original_ip,
+            bytes: memory.fetch(self.cs.0, original_ip, usize::from(instruction.length)),
+            instruction: instruction.to_string(),
+            flags_before: self.flags.clone(),
+            ..Trace::default()
+        };
+        let jump_taken = match &instruction.instruction_category {
+            decode::InstructionCategory::Jump(mnemonic, _) => self.jump_taken(mnemonic),
+            decode::InstructionCategory::NoOperands(Mnemonic::INTO) => self.flags.of,
+            _ => false,
+        };
+
+        let shift_count = match &instruction.instruction_category {
+            decode::InstructionCategory::Shift(_, _, _, true) => {
+                self.get_register(&RegisterName::CL)
+            }
+            _ => 0,
+        };
+        let original_cx = self.cx.0;
+        let (original_si, original_di) = (self.si.0, self.di.0);
+        // Every word transfer costs 4 more clocks and a second bus cycle on
+        // the 8088's 8-bit bus, and on the 8086 when the address is odd.
+        let operand_offset =
+            instruction
+                .memory_operand()
+                .map(|(operand, word_operation)| {
+                    match self.resolve(operand, instruction.segment_override.as_ref()) {
+                        Location::Memory { offset, .. } => (offset, word_operation),
+                        Location::Register(_) => (0, word_operation),
+                    }
+                });
+        let interrupts = match &instruction.instruction_category {
+            decode::InstructionCategory::Immediate(Mnemonic::INT, _)
+            | decode::InstructionCategory::NoOperands(Mnemonic::INT3) => true,
+            decode::InstructionCategory::NoOperands(Mnemonic::INTO) => jump_taken,
+            _ => false,
+        };
+
+        let original_sp = self.sp.0;
+        let segment_override = instruction.segment_override.as_ref();
 
         match &instruction.instruction_category {
             decode::InstructionCategory::RegisterMemoryAndRegister(mnemonic, src, dest) => {
+                let word_operation = match (src, dest) {
+                    (_, RegisterMemory::Register(name)) | (RegisterMemory::Register(name), _) => {
+                        name.is_word()
+                    }
+                    _ => return Err(unsupported()),
+                };
+                let src = self.resolve(src, segment_override);
+                let dest = self.resolve(dest, segment_override);
                 match mnemonic {
-                    decode::Mnemonic::MOV => match (src, dest) {
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let new_value = self.get_register(src_name).1;
-                            self.set_register(dest_name, new_value, false);
-                        }
-                        (
-                            RegisterMemory::DirectAddress(address),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let value: u16 = u16::from_le_bytes([
-                                memory[*address as usize],
-                                memory[(*address + 1) as usize],
-                            ]);
-                            self.set_register(dest_name, value, true);
-                        }
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::RegisterAddressOffset(dest_name, offset_name),
-                        ) => {
-                            let src_value = self.get_register(src_name).1;
-                            let mut address = self.get_register(dest_name).1;
-                            let offset = self.get_register(offset_name).1;
-                            address += offset;
-                            let bytes = src_value.to_le_bytes();
-                            memory[address as usize] = bytes[0];
-                            memory[(address + 1) as usize] = bytes[1];
-                        }
-                        (
-                            RegisterMemory::RegisterAddressOffset(src_name, offset_name),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let mut address = self.get_register(src_name).1;
-                            let offset = self.get_register(offset_name).1;
-                            address += offset;
-                            let value = u16::from_le_bytes([
-                                memory[address as usize],
-                                memory[(address + 1) as usize],
-                            ]);
-                            self.set_register(dest_name, value, true);
-                        }
-                        (
-                            RegisterMemory::RegisterAddressDisplacement(src_name, displacement),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let address = self.get_register(src_name).1 + displacement;
-                            let value = u16::from_le_bytes([
-                                memory[address as usize],
-                                memory[(address + 1) as usize],
-                            ]);
-                            self.set_register(dest_name, value, true);
-                        }
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::RegisterAddressDisplacement(dest_name, displacement),
-                        ) => {
-                            let value = self.get_register(src_name).1;
-                            let address = self.get_register(dest_name).1 + displacement;
-                            let bytes = value.to_le_bytes();
-                            memory[address as usize] = bytes[0];
-                            memory[(address + 1) as usize] = bytes[1];
-                        }
-                        (
-                            RegisterMemory::RegisterAddress(src_name),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let address = self.get_register(src_name).1;
-                            let value = u16::from_le_bytes([
-                                memory[address as usize],
-                                memory[(address + 1) as usize],
-                            ]);
-                            self.set_register(dest_name, value, true);
-                        }
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::RegisterAddress(dest_name),
-                        ) => {
-                            let value = self.get_register(src_name).1;
-                            let address = self.get_register(dest_name).1;
-                            let bytes = value.to_le_bytes();
-                            memory[address as usize] = bytes[0];
-                            memory[(address + 1) as usize] = bytes[1];
-                        }
-                        _ => todo!(),
-                    },
-                    decode::Mnemonic::ADD => match (src, dest) {
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let new_value = self
-                                .get_register(dest_name)
-                                .1
-                                .overflowing_add(self.get_register(src_name).1)
-                                .0;
-                            self.set_register(dest_name, new_value, true);
-                        }
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::RegisterAddressDisplacement(dest_name, displacement),
-                        ) => {
-                            let a = self.get_register(src_name).1;
-                            let address = self.get_register(dest_name).1 + displacement;
-                            let b = u16::from_le_bytes([
-                                memory[address as usize],
-                                memory[(address + 1) as usize],
-                            ]);
-
-                            let value = a.overflowing_add(b).0;
-
-                            let bytes = value.to_le_bytes();
-                            memory[address as usize] = bytes[0];
-                            memory[(address + 1) as usize] = bytes[1];
-                        }
-                        _ => todo!(),
-                    },
-                    decode::Mnemonic::SUB => match (src, dest) {
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let new_value = self
-                                .get_register(dest_name)
-                                .1
-                                .overflowing_sub(self.get_register(src_name).1)
-                                .0;
-                            self.set_register(dest_name, new_value, true);
+                    Mnemonic::MOV => {
+                        let value = self.read(&src, memory, word_operation);
+                        self.write(&dest, memory, value, word_operation);
+                    }
+                    Mnemonic::XCHG => {
+                        let a = self.read(&dest, memory, word_operation);
+                        let b = self.read(&src, memory, word_operation);
+                        self.write(&dest, memory, b, word_operation);
+                        self.write(&src, memory, a, word_operation);
+                    }
+                    Mnemonic::LEA | Mnemonic::LDS | Mnemonic::LES => {
+                        let Location::Memory { segment, offset } = src else {
+                            return Err(unsupported());
+                        };
+                        if *mnemonic == Mnemonic::LEA {
+                            self.write(&dest, memory, offset, true);
+                        } else {
+                            let value = memory.read(segment, offset, true);
+                            let segment = memory.read(segment, offset.wrapping_add(2), true);
+                            self.write(&dest, memory, value, true);
+                            let segment_register = match mnemonic {
+                                Mnemonic::LDS => RegisterName::DS,
+                                _ => RegisterName::ES,
+                            };
+                            self.set_register(&segment_register, segment);
                         }
-                        _ => todo!(),
-                    },
-                    decode::Mnemonic::CMP => match (src, dest) {
-                        (
-                            RegisterMemory::Register(src_name),
-                            RegisterMemory::Register(dest_name),
-                        ) => {
-                            let a = self.get_register(src_name).1;
-                            let b = self.get_register(dest_name).1;
-                            self.update_flags(a, b);
+                    }
+                    mnemonic if alu_operation(mnemonic) => {
+                        let a = self.read(&dest, memory, word_operation);
+                        let b = self.read(&src, memory, word_operation);
+                        if let Some(value) = self.arithmetic(mnemonic, a, b, word_operation) {
+                            self.write(&dest, memory, value, word_operation);
                         }
-                        _ => todo!(),
-                    },
-                    _ => todo!(),
-                };
+                    }
+                    _ => return Err(unsupported()),
+                }
             }
-            decode::InstructionCategory::ImmediateToRegister(mnemonic, immediate, dest) => {
-                match mnemonic {
-                    decode::Mnemonic::MOV => self.set_register(dest, *immediate, false),
-                    _ => todo!(),
-                };
+            decode::InstructionCategory::ImmediateToRegister(Mnemonic::MOV, immediate, dest) => {
+                self.set_register(dest, *immediate);
             }
             decode::InstructionCategory::ImmediateToRegisterMemory(
                 mnemonic,
                 immediate,
                 dest,
-                _word_operation,
-            ) => match mnemonic {
-                decode::Mnemonic::ADD => match dest {
-                    RegisterMemory::Register(dest_name) => {
-                        let dest_value = self.get_register(dest_name).1;
-                        self.set_register(dest_name, dest_value + *immediate, true);
-                    }
-                    _ => todo!(),
-                },
-                decode::Mnemonic::SUB => match dest {
-                    RegisterMemory::Register(dest_name) => {
-                        let dest_value = self.get_register(dest_name).1;
-                        self.set_register(dest_name, dest_value - *immediate, true);
-                    }
-                    _ => todo!(),
-                },
-                decode::Mnemonic::MOV => match dest {
-                    RegisterMemory::DirectAddress(address) => {
-                        let bytes = immediate.to_le_bytes();
-                        memory[*address as usize] = bytes[0];
-                        memory[(*address + 1) as usize] = bytes[1];
+                word_operation,
+            ) => {
+                let dest = self.resolve(dest, segment_override);
+                match mnemonic {
+                    Mnemonic::MOV => self.write(&dest, memory, *immediate, *word_operation),
+                    mnemonic if alu_operation(mnemonic) => {
+                        let a = self.read(&dest, memory, *word_operation);
+                        if let Some(value) =
+                            self.arithmetic(mnemonic, a, *immediate, *word_operation)
+                        {
+                            self.write(&dest, memory, value, *word_operation);
+                        }
                     }
-                    RegisterMemory::RegisterAddressDisplacement(dest_name, displacement) => {
-                        let mut address = self.get_register(dest_name).1;
-                        address += displacement;
-                        let bytes = immediate.to_le_bytes();
-                        memory[address as usize] = bytes[0];
-                        memory[(address + 1) as usize] = bytes[1];
+                    _ => return Err(unsupported()),
+                }
+            }
+            decode::InstructionCategory::ImmediateToAccumulator(mnemonic, immediate, dest)
+                if alu_operation(mnemonic) =>
+            {
+                let a = self.get_register(dest);
+                let word_operation = *dest == RegisterName::AX;
+                if let Some(value) = self.arithmetic(mnemonic, a, *immediate, word_operation) {
+                    self.set_register(dest, value);
+                }
+            }
+            decode::InstructionCategory::SingleOperand(
+                mnemonic @ (Mnemonic::INC | Mnemonic::DEC | Mnemonic::NEG | Mnemonic::NOT),
+                operand,
+                word_operation,
+            ) => {
+                let operand = self.resolve(operand, segment_override);
+                let value = self.read(&operand, memory, *word_operation);
+                let carry = self.flags.cf;
+                let result = match mnemonic {
+                    Mnemonic::INC => self.arithmetic(&Mnemonic::ADD, value, 1, *word_operation),
+                    Mnemonic::DEC => self.arithmetic(&Mnemonic::SUB, value, 1, *word_operation),
+                    Mnemonic::NEG => self.arithmetic(&Mnemonic::SUB, 0, value, *word_operation),
+                    _ => Some(!value),
+                };
+                if matches!(mnemonic, Mnemonic::INC | Mnemonic::DEC) {
+                    self.flags.cf = carry;
+                }
+                if let Some(result) = result {
+                    self.write(&operand, memory, result, *word_operation);
+                }
+            }
+            decode::InstructionCategory::SingleOperand(
+                mnemonic @ (Mnemonic::MUL | Mnemonic::IMUL | Mnemonic::DIV | Mnemonic::IDIV),
+                operand,
+                word_operation,
+            ) => {
+                let operand = self.resolve(operand, segment_override);
+                let value = self.read(&operand, memory, *word_operation);
+                match mnemonic {
+                    Mnemonic::MUL | Mnemonic::IMUL => {
+                        self.multiply(mnemonic, value, *word_operation)
                     }
-                    _ => todo!(),
-                },
-                decode::Mnemonic::CMP => match dest {
-                    RegisterMemory::Register(dest_name) => {
-                        let dest_value = self.get_register(dest_name).1;
-                        self.update_flags(*immediate, dest_value);
+                    _ => {
+                        if !self.divide(mnemonic, value, *word_operation) {
+                            self.interrupt(0, memory, interrupt_handler)?;
+                        }
                     }
-                    _ => todo!(),
-                },
-                _ => todo!(),
+                }
+            }
+            decode::InstructionCategory::Shift(mnemonic, operand, word_operation, by_cl) => {
+                let count = if *by_cl { shift_count } else { 1 };
+                let operand = self.resolve(operand, segment_override);
+                let value = self.read(&operand, memory, *word_operation);
+                if count != 0 {
+                    let result = self.shift(mnemonic, value, count, *word_operation);
+                    self.write(&operand, memory, result, *word_operation);
+                }
+            }
+            decode::InstructionCategory::NoOperands(
+                mnemonic @ (Mnemonic::CLC
+                | Mnemonic::STC
+                | Mnemonic::CMC
+                | Mnemonic::CLD
+                | Mnemonic::STD
+                | Mnemonic::CLI
+                | Mnemonic::STI),
+            ) => match mnemonic {
+                Mnemonic::CLC => self.flags.cf = false,
+                Mnemonic::STC => self.flags.cf = true,
+                Mnemonic::CMC => self.flags.cf = !self.flags.cf,
+                Mnemonic::CLD => self.flags.df = false,
+                Mnemonic::STD => self.flags.df = true,
+                Mnemonic::CLI => self.flags.if_ = false,
+                _ => self.flags.if_ = true,
             },
-            decode::InstructionCategory::ImmediateToAccumulator(_mnemonic, _immediate, _dest) => {
-                todo!()
-            }
-            decode::InstructionCategory::Jump(mnemonic, increment) => match mnemonic {
-                decode::Mnemonic::JNZ => {
-                    if !self.flags.zf {
-                        let increment = i16::from(*increment);
-                        let new_ip = self
-                            .ip
-                            .checked_add_signed(increment.into())
-                            .expect("jnz increment should not overflow instruction pointer");
-                        self.ip = new_ip;
+            decode::InstructionCategory::NoOperands(mnemonic @ (Mnemonic::DAA | Mnemonic::DAS)) => {
+                self.decimal_adjust(mnemonic);
+            }
+            decode::InstructionCategory::NoOperands(mnemonic @ (Mnemonic::AAA | Mnemonic::AAS)) => {
+                self.ascii_adjust(mnemonic);
+            }
+            decode::InstructionCategory::NoOperands(mnemonic @ (Mnemonic::AAM | Mnemonic::AAD)) => {
+                if !self.ascii_adjust_base(mnemonic, 10) {
+                    self.interrupt(0, memory, interrupt_handler)?;
+                }
+            }
+            decode::InstructionCategory::Immediate(
+                mnemonic @ (Mnemonic::AAM | Mnemonic::AAD),
+                base,
+            ) => {
+                if !self.ascii_adjust_base(mnemonic, *base) {
+                    self.interrupt(0, memory, interrupt_handler)?;
+                }
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::CBW) => {
+                let value = self.get_register(&RegisterName::AL) as u8 as i8;
+                self.set_register(&RegisterName::AX, i16::from(value) as u16);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::CWD) => {
+                let value = if self.ax.0 & 0x8000 != 0 { 0xFFFF } else { 0 };
+                self.set_register(&RegisterName::DX, value);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::LAHF) => {
+                let value = u16::from(&self.flags) & 0xFF;
+                self.set_register(&RegisterName::AH, value);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::SAHF) => {
+                let value = self.get_register(&RegisterName::AH);
+                self.flags = Flags::from((u16::from(&self.flags) & 0xFF00) | value);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::XLAT) => {
+                let segment = self.get_register(segment_override.unwrap_or(&RegisterName::DS));
+                let offset = self.bx.0.wrapping_add(self.get_register(&RegisterName::AL));
+                let value = memory.read(segment, offset, false);
+                self.set_register(&RegisterName::AL, value);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::HLT) => {
+                self.halted = true;
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::NOP | Mnemonic::WAIT) => {}
+            decode::InstructionCategory::Jump(mnemonic, increment) => {
+                if matches!(
+                    mnemonic,
+                    Mnemonic::LOOP | Mnemonic::LOOPE | Mnemonic::LOOPNE
+                ) {
+                    let count = self.cx.0.wrapping_sub(1);
+                    self.set_register(&RegisterName::CX, count);
+                }
+                if jump_taken {
+                    self.ip = self.ip.wrapping_add_signed(i16::from(*increment));
+                }
+            }
+            decode::InstructionCategory::NearJump(Mnemonic::JMP, increment) => {
+                self.ip = self.ip.wrapping_add_signed(*increment);
+            }
+            decode::InstructionCategory::SingleOperand(Mnemonic::JMP, operand, _) => {
+                let operand = self.resolve(operand, segment_override);
+                self.ip = self.read(&operand, memory, true);
+            }
+            decode::InstructionCategory::FarJump(Mnemonic::JMP, segment, offset) => {
+                self.set_register(&RegisterName::CS, *segment);
+                self.ip = *offset;
+            }
+            decode::InstructionCategory::IndirectFar(Mnemonic::JMP, operand) => {
+                let Location::Memory { segment, offset } = self.resolve(operand, segment_override)
+                else {
+                    return Err(unsupported());
+                };
+                self.ip = memory.read(segment, offset, true);
+                let segment = memory.read(segment, offset.wrapping_add(2), true);
+                self.set_register(&RegisterName::CS, segment);
+            }
+            decode::InstructionCategory::StringManipulation(mnemonic, word_operation) => {
+                self.string_operation(instruction, mnemonic, *word_operation, memory);
+            }
+            decode::InstructionCategory::SingleOperand(Mnemonic::PUSH, operand, _) => {
+                // The 8086 pushes the already decremented value of SP.
+                let value = match operand {
+                    RegisterMemory::Register(RegisterName::SP) => self.sp.0.wrapping_sub(2),
+                    _ => {
+                        let operand = self.resolve(operand, segment_override);
+                        self.read(&operand, memory, true)
                     }
+                };
+                self.push(memory, value);
+            }
+            decode::InstructionCategory::SingleOperand(Mnemonic::POP, operand, _) => {
+                let value = self.pop(memory);
+                let operand = self.resolve(operand, segment_override);
+                self.write(&operand, memory, value, true);
+            }
+            decode::InstructionCategory::SingleOperand(Mnemonic::CALL, operand, _) => {
+                let operand = self.resolve(operand, segment_override);
+                let target = self.read(&operand, memory, true);
+                self.push(memory, self.ip);
+                self.ip = target;
+            }
+            decode::InstructionCategory::NearJump(Mnemonic::CALL, increment) => {
+                self.push(memory, self.ip);
+                self.ip = self.ip.wrapping_add_signed(*increment);
+            }
+            decode::InstructionCategory::FarJump(Mnemonic::CALL, segment, offset) => {
+                self.push(memory, self.cs.0);
+                self.push(memory, self.ip);
+                self.set_register(&RegisterName::CS, *segment);
+                self.ip = *offset;
+            }
+            decode::InstructionCategory::IndirectFar(Mnemonic::CALL, operand) => {
+                let (offset, segment) = match self.resolve(operand, segment_override) {
+                    Location::Memory { segment, offset } => (
+                        memory.read(segment, offset, true),
+                        memory.read(segment, offset.wrapping_add(2), true),
+                    ),
+                    Location::Register(_) => return Err(unsupported()),
+                };
+                self.push(memory, self.cs.0);
+                self.push(memory, self.ip);
+                self.set_register(&RegisterName::CS, segment);
+                self.ip = offset;
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::RET) => {
+                self.ip = self.pop(memory);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::RETF) => {
+                self.ip = self.pop(memory);
+                let segment = self.pop(memory);
+                self.set_register(&RegisterName::CS, segment);
+            }
+            decode::InstructionCategory::Immediate(
+                mnemonic @ (Mnemonic::RET | Mnemonic::RETF),
+                count,
+            ) => {
+                self.ip = self.pop(memory);
+                if *mnemonic == Mnemonic::RETF {
+                    let segment = self.pop(memory);
+                    self.set_register(&RegisterName::CS, segment);
                 }
-                _ => todo!(),
-            },
+                self.sp.0 = self.sp.0.wrapping_add(*count);
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::PUSHF) => {
+                self.push(memory, u16::from(&self.flags));
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::POPF) => {
+                let value = self.pop(memory);
+                self.flags = Flags::from(value);
+            }
+            decode::InstructionCategory::Immediate(Mnemonic::INT, vector) => {
+                self.interrupt(*vector as u8, memory, interrupt_handler)?;
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::INT3) => {
+                self.interrupt(3, memory, interrupt_handler)?;
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::INTO) => {
+                if jump_taken {
+                    self.interrupt(4, memory, interrupt_handler)?;
+                }
+            }
+            decode::InstructionCategory::NoOperands(Mnemonic::IRET) => {
+                self.ip = self.pop(memory);
+                let segment = self.pop(memory);
+                self.set_register(&RegisterName::CS, segment);
+                let value = self.pop(memory);
+                self.flags = Flags::from(value);
+            }
+            decode::InstructionCategory::FixedPort(mnemonic, port, register) => {
+                self.port_transfer(mnemonic, u16::from(*port), register, memory);
+            }
+            decode::InstructionCategory::VariablePort(mnemonic, register) => {
+                let port = self.dx.0;
+                self.port_transfer(mnemonic, port, register, memory);
+            }
+            _ => return Err(unsupported()),
         };
 
-        let original_ip = self.ip - u16::from(instruction.length);
-        print!(" ip:{:#x}->{:#x}", original_ip, self.ip);
-        if original_flags != self.flags {
-            println!(" flags:{}->{}", original_flags, self.flags)
-        } else {
-            println!();
+        let stack_operation = match &instruction.instruction_category {
+            decode::InstructionCategory::SingleOperand(Mnemonic::POP, operand, _) => {
+                *operand != RegisterMemory::Register(RegisterName::SP)
+            }
+            decode::InstructionCategory::SingleOperand(Mnemonic::PUSH | Mnemonic::CALL, _, _)
+            | decode::InstructionCategory::NearJump(Mnemonic::CALL, _)
+            | decode::InstructionCategory::FarJump(Mnemonic::CALL, _, _)
+            | decode::InstructionCategory::IndirectFar(Mnemonic::CALL, _)
+            | decode::InstructionCategory::Immediate(_, _)
+            | decode::InstructionCategory::NoOperands(_) => true,
+            _ => false,
+        };
+        if stack_operation && self.sp.0 != original_sp {
+            self.trace.registers.push(RegisterChange {
+                register: RegisterName::SP,
+                old: original_sp,
+                new: self.sp.0,
+            });
         }
+        // Repeated string instructions are charged for the iterations that
+        // actually ran, since REPE and REPNE can stop before CX runs out.
+        let repetitions = original_cx.wrapping_sub(self.cx.0);
+        let count = match &instruction.instruction_category {
+            decode::InstructionCategory::StringManipulation(_, _) => repetitions,
+            _ => shift_count,
+        };
+        let clocks = instruction.clocks(jump_taken, count);
+        let model = self.model;
+        let mut transfers = 0;
+        let mut split_transfers = 0;
+        let mut transfer = |count: u32, word: bool, offset: u16| {
+            transfers += count;
+            if word && (model == CpuModel::Intel8088 || offset % 2 == 1) {
+                split_transfers += count;
+            }
+        };
+        if let Some((offset, word_operation)) = operand_offset {
+            transfer(
+                u32::from(instruction.memory_transfers()),
+                word_operation,
+                offset,
+            );
+        }
+        if interrupts
+            || !matches!(
+                &instruction.instruction_category,
+                decode::InstructionCategory::NoOperands(Mnemonic::INTO)
+            )
+        {
+            transfer(u32::from(instruction.stack_transfers()), true, original_sp);
+        }
+        if interrupts {
+            // The new CS:IP from the vector table.
+            transfer(2, true, 0);
+        }
+        if let decode::InstructionCategory::StringManipulation(_, word_operation) =
+            &instruction.instruction_category
+        {
+            let repetitions = if instruction.repeat.is_some() {
+                u32::from(repetitions)
+            } else {
+                1
+            };
+            let (source, destination) = instruction.string_transfers();
+            transfer(
+                u32::from(source) * repetitions,
+                *word_operation,
+                original_si,
+            );
+            transfer(
+                u32::from(destination) * repetitions,
+                *word_operation,
+                original_di,
+            );
+        }
+        let penalty = 4 * split_transfers;
+        let total = clocks.base + u32::from(clocks.effective_address) + penalty;
+        self.cycle_count += total;
+        self.trace.clocks = TraceClocks {
+            base: clocks.base,
+            effective_address: clocks.effective_address,
+            penalty,
+            total,
+            cycle_count: self.cycle_count,
+        };
+
+        self.trace.next_ip = self.ip;
+        self.trace.flags_after = self.flags.clone();
+
+        if let Some(prefetch_queue) = &mut self.prefetch_queue {
+            let length = u16::from(instruction.length);
+            let bus_cycles = transfers + split_transfers;
+            let elapsed = prefetch_queue.execute(
+                length,
+                total,
+                bus_cycles,
+                self.ip,
+                self.ip == original_ip.wrapping_add(length),
+            );
+            self.trace.prefetch = Some(PrefetchClocks {
+                elapsed,
+                clock: prefetch_queue.clock(),
+            });
+        }
+        Ok(std::mem::take(&mut self.trace))
+    }
+
+    fn jump_taken(&self, mnemonic: &Mnemonic) -> bool {
+        let flags = &self.flags;
+        match mnemonic {
+            Mnemonic::JO => flags.of,
+            Mnemonic::JNO => !flags.of,
+            Mnemonic::JC => flags.cf,
+            Mnemonic::JNC => !flags.cf,
+            Mnemonic::JZ => flags.zf,
+            Mnemonic::JNZ => !flags.zf,
+            Mnemonic::JNA => flags.cf || flags.zf,
+            Mnemonic::JA => !flags.cf && !flags.zf,
+            Mnemonic::JS => flags.sf,
+            Mnemonic::JNS => !flags.sf,
+            Mnemonic::JPE => flags.pf,
+            Mnemonic::JPO => !flags.pf,
+            Mnemonic::JL => flags.sf != flags.of,
+            Mnemonic::JNL => flags.sf == flags.of,
+            Mnemonic::JNG => flags.zf || flags.sf != flags.of,
+            Mnemonic::JG => !flags.zf && flags.sf == flags.of,
+            Mnemonic::LOOP => self.cx.0 != 1,
+            Mnemonic::LOOPE => self.cx.0 != 1 && flags.zf,
+            Mnemonic::LOOPNE => self.cx.0 != 1 && !flags.zf,
+            Mnemonic::JCXZ => self.cx.0 == 0,
+            Mnemonic::JMP => true,
+            _ => false,
+        }
+    }
+
+    fn interrupt(
+        &mut self,
+        vector: u8,
+        memory: &mut Memory,
+        interrupt_handler: Option<&mut dyn InterruptHandler>,
+    ) -> Result<(), SimulationError> {
+        if let Some(interrupt_handler) = interrupt_handler {
+            match interrupt_handler.interrupt(vector, self, memory) {
+                InterruptResult::Unhandled => {}
+                InterruptResult::Handled => return Ok(()),
+                InterruptResult::Exit(exit_code) => {
+                    self.exit_code = Some(exit_code);
+                    self.trace.exit_code = Some(exit_code);
+                    return Ok(());
+                }
+                InterruptResult::OutputFailed(error) => return Err(SimulationError::Output(error)),
+            }
+        }
+
+        // Nothing installs a handler at 0:0, and jumping there would run the
+        // vector table itself as code.
+        let vector_address = u16::from(vector) * 4;
+        if memory.read(0, vector_address, true) == 0
+            && memory.read(0, vector_address + 2, true) == 0
+        {
+            return Err(SimulationError::UnhandledInterrupt(vector));
+        }
+
+        self.push(memory, u16::from(&self.flags));
+        self.flags.if_ = false;
+        self.flags.tf = false;
+        self.push(memory, self.cs.0);
+        self.push(memory, self.ip);
+        self.ip = memory.read(0, vector_address, true);
+        let segment = memory.read(0, vector_address + 2, true);
+        self.set_register(&RegisterName::CS, segment);
+        Ok(())
+    }
+
+    // A word transfer uses `port` for the low byte and the next port for the
+    // high byte.
+    fn port_transfer(
+        &mut self,
+        mnemonic: &Mnemonic,
+        port: u16,
+        register: &RegisterName,
+        memory: &mut Memory,
+    ) {
+        let bus = memory.bus_mut();
+        match mnemonic {
+            Mnemonic::IN => {
+                let low = bus.input(port);
+                let value = if register.is_word() {
+                    u16::from_le_bytes([low, bus.input(port.wrapping_add(1))])
+                } else {
+                    u16::from(low)
+                };
+                self.set_register(register, value);
+            }
+            _ => {
+                let [low, high] = self.get_register(register).to_le_bytes();
+                bus.output(port, low);
+                if register.is_word() {
+                    bus.output(port.wrapping_add(1), high);
+                }
+            }
+        }
+    }
+
+    // These move SP without tracing it; execute reports the net change once.
+    fn push(&mut self, memory: &mut Memory, value: u16) {
+        self.sp.0 = self.sp.0.wrapping_sub(2);
+        self.write_memory(memory, self.ss.0, self.sp.0, value, true);
+    }
+
+    fn pop(&mut self, memory: &Memory) -> u16 {
+        let value = memory.read(self.ss.0, self.sp.0, true);
+        self.sp.0 = self.sp.0.wrapping_add(2);
+        value
     }
 
-    fn get_register(&mut self, name: &RegisterName) -> &mut Register {
+    fn register(&self, name: &RegisterName) -> &Register {
         match name {
-            RegisterName::AX => &mut self.ax,
-            RegisterName::BX => &mut self.bx,
-            RegisterName::CX => &mut self.cx,
-            RegisterName::DX => &mut self.dx,
+            RegisterName::AX | RegisterName::AL | RegisterName::AH => &self.ax,
+            RegisterName::BX | RegisterName::BL | RegisterName::BH => &self.bx,
+            RegisterName::CX | RegisterName::CL | RegisterName::CH => &self.cx,
+            RegisterName::DX | RegisterName::DL | RegisterName::DH => &self.dx,
+            RegisterName::BP => &self.bp,
+            RegisterName::SP => &self.sp,
+            RegisterName::DI => &self.di,
+            RegisterName::SI => &self.si,
+            RegisterName::ES => &self.es,
+            RegisterName::CS => &self.cs,
+            RegisterName::SS => &self.ss,
+            RegisterName::DS => &self.ds,
+        }
+    }
+
+    fn register_mut(&mut self, name: &RegisterName) -> &mut Register {
+        match name {
+            RegisterName::AX | RegisterName::AL | RegisterName::AH => &mut self.ax,
+            RegisterName::BX | RegisterName::BL | RegisterName::BH => &mut self.bx,
+            RegisterName::CX | RegisterName::CL | RegisterName::CH => &mut self.cx,
+            RegisterName::DX | RegisterName::DL | RegisterName::DH => &mut self.dx,
             RegisterName::BP => &mut self.bp,
             RegisterName::SP => &mut self.sp,
             RegisterName::DI => &mut self.di,
             RegisterName::SI => &mut self.si,
-            _ => todo!(),
+            RegisterName::ES => &mut self.es,
+            RegisterName::CS => &mut self.cs,
+            RegisterName::SS => &mut self.ss,
+            RegisterName::DS => &mut self.ds,
+        }
+    }
+
+    pub fn get_register(&self, name: &RegisterName) -> u16 {
+        let value = self.register(name).0;
+        match name {
+            RegisterName::AL | RegisterName::BL | RegisterName::CL | RegisterName::DL => {
+                value & 0x00FF
+            }
+            RegisterName::AH | RegisterName::BH | RegisterName::CH | RegisterName::DH => value >> 8,
+            _ => value,
+        }
+    }
+
+    pub fn set_register(&mut self, dest: &RegisterName, value: u16) {
+        let prev = self.get_register(dest);
+        let register = self.register_mut(dest);
+        register.0 = match dest {
+            RegisterName::AL | RegisterName::BL | RegisterName::CL | RegisterName::DL => {
+                (register.0 & 0xFF00) | (value & 0x00FF)
+            }
+            RegisterName::AH | RegisterName::BH | RegisterName::CH | RegisterName::DH => {
+                (register.0 & 0x00FF) | (value << 8)
+            }
+            _ => value,
+        };
+        let value = self.get_register(dest);
+
+        if prev != value {
+            self.trace.registers.push(RegisterChange {
+                register: *dest,
+                old: prev,
+                new: value,
+            });
         }
     }
 
-    fn set_register(&mut self, dest: &RegisterName, value: u16, set_flags: bool) {
-        let register = self.get_register(dest);
-        let prev = register.1;
-        register.1 = value;
+    fn resolve(
+        &mut self,
+        register_memory: &RegisterMemory,
+        segment_override: Option<&RegisterName>,
+    ) -> Location {
+        let (offset, default_segment) = match register_memory {
+            RegisterMemory::Register(name) => return Location::Register(*name),
+            RegisterMemory::DirectAddress(address) => (*address, RegisterName::DS),
+            RegisterMemory::RegisterAddress(name) => (self.get_register(name), RegisterName::DS),
+            RegisterMemory::RegisterAddressDisplacement(name, displacement) => (
+                self.get_register(name).wrapping_add_signed(*displacement),
+                default_segment(name),
+            ),
+            RegisterMemory::RegisterAddressOffset(name, offset_name) => (
+                self.get_register(name)
+                    .wrapping_add(self.get_register(offset_name)),
+                default_segment(name),
+            ),
+            RegisterMemory::RegisterAddressOffsetDisplacement(name, offset_name, displacement) => (
+                self.get_register(name)
+                    .wrapping_add(self.get_register(offset_name))
+                    .wrapping_add_signed(*displacement),
+                default_segment(name),
+            ),
+        };
+        let segment = segment_override.unwrap_or(&default_segment);
+        Location::Memory {
+            segment: self.get_register(segment),
+            offset,
+        }
+    }
+
+    fn read(&mut self, location: &Location, memory: &Memory, word_operation: bool) -> u16 {
+        match location {
+            Location::Register(name) => self.get_register(name),
+            Location::Memory { segment, offset } => memory.read(*segment, *offset, word_operation),
+        }
+    }
 
-        let new_flags = Flags {
-            sf: (value & 0x8000) != 0,
-            zf: value == 0,
+    fn write(
+        &mut self,
+        location: &Location,
+        memory: &mut Memory,
+        value: u16,
+        word_operation: bool,
+    ) {
+        match location {
+            Location::Register(name) => self.set_register(name, value),
+            Location::Memory { segment, offset } => {
+                self.write_memory(memory, *segment, *offset, value, word_operation)
+            }
+        }
+    }
+
+    fn write_memory(
+        &mut self,
+        memory: &mut Memory,
+        segment: u16,
+        offset: u16,
+        value: u16,
+        word: bool,
+    ) {
+        memory.write(segment, offset, value, word);
+        self.trace.memory.push(MemoryWrite {
+            segment,
+            offset,
+            value: if word { value } else { value & 0x00FF },
+            word,
+        });
+    }
+
+    fn arithmetic(
+        &mut self,
+        mnemonic: &Mnemonic,
+        dest: u16,
+        src: u16,
+        word_operation: bool,
+    ) -> Option<u16> {
+        let (mask, sign) = if word_operation {
+            (0xFFFF, 0x8000)
+        } else {
+            (0xFF, 0x80)
+        };
+        let a = u32::from(dest) & mask;
+        let b = u32::from(src) & mask;
+        let carry = u32::from(self.flags.cf);
+
+        let result = match mnemonic {
+            Mnemonic::ADD | Mnemonic::ADC => {
+                let carry = if *mnemonic == Mnemonic::ADC { carry } else { 0 };
+                let result = a + b + carry;
+                self.flags.cf = result > mask;
+                self.flags.af = ((a ^ b ^ result) & 0x10) != 0;
+                self.flags.of = ((a ^ result) & (b ^ result) & sign) != 0;
+                result & mask
+            }
+            Mnemonic::SUB | Mnemonic::SBB | Mnemonic::CMP => {
+                let borrow = if *mnemonic == Mnemonic::SBB { carry } else { 0 };
+                let result = a.wrapping_sub(b).wrapping_sub(borrow) & mask;
+                self.flags.cf = a < b + borrow;
+                self.flags.af = ((a ^ b ^ result) & 0x10) != 0;
+                self.flags.of = ((a ^ b) & (a ^ result) & sign) != 0;
+                result
+            }
+            // AND, TEST, OR and XOR; callers check `alu_operation` first.
+            _ => {
+                self.flags.cf = false;
+                self.flags.af = false;
+                self.flags.of = false;
+                match mnemonic {
+                    Mnemonic::OR => a | b,
+                    Mnemonic::XOR => a ^ b,
+                    _ => a & b,
+                }
+            }
         };
 
-        if set_flags && self.flags != new_flags {
-            self.flags = new_flags;
+        self.result_flags(result as u16, word_operation);
+
+        match mnemonic {
+            Mnemonic::CMP | Mnemonic::TEST => None,
+            _ => Some(result as u16),
         }
+    }
 
-        if prev != value {
-            print!(" {}:{:#x}->{:#x}", dest, prev, value);
+    // ZF, SF and PF, which every operation that sets them derives from the
+    // result alone.
+    fn result_flags(&mut self, result: u16, word_operation: bool) {
+        let (mask, sign) = if word_operation {
+            (0xFFFF, 0x8000)
+        } else {
+            (0xFF, 0x80)
+        };
+        self.flags.zf = result & mask == 0;
+        self.flags.sf = (result & sign) != 0;
+        self.flags.pf = (result as u8).count_ones().is_multiple_of(2);
+    }
+
+    // Shifts and rotates one bit at a time, `count` times. OF is only defined
+    // for single-bit operations; for longer ones it reflects the last step, as
+    // on the 8086. AF is undefined after shifts and keeps its value. Rotates
+    // only change CF and OF.
+    fn shift(&mut self, mnemonic: &Mnemonic, value: u16, count: u16, word_operation: bool) -> u16 {
+        let (mask, sign) = if word_operation {
+            (0xFFFF, 0x8000)
+        } else {
+            (0xFF, 0x80)
+        };
+        let mut value = u32::from(value) & mask;
+        for _ in 0..count {
+            let high = value & sign != 0;
+            let low = value & 1 != 0;
+            let (result, carry) = match mnemonic {
+                Mnemonic::ROL => ((value << 1 | u32::from(high)) & mask, high),
+                Mnemonic::ROR => (value >> 1 | if low { sign } else { 0 }, low),
+                Mnemonic::RCL => ((value << 1 | u32::from(self.flags.cf)) & mask, high),
+                Mnemonic::RCR => (value >> 1 | if self.flags.cf { sign } else { 0 }, low),
+                Mnemonic::SHL => ((value << 1) & mask, high),
+                Mnemonic::SHR => (value >> 1, low),
+                _ => (value >> 1 | value & sign, low),
+            };
+            self.flags.of = match mnemonic {
+                Mnemonic::SHR => high,
+                Mnemonic::SAR => false,
+                Mnemonic::ROR | Mnemonic::RCR => (result ^ result << 1) & sign != 0,
+                _ => (result & sign != 0) != carry,
+            };
+            self.flags.cf = carry;
+            value = result;
         }
+        if matches!(mnemonic, Mnemonic::SHL | Mnemonic::SHR | Mnemonic::SAR) {
+            self.result_flags(value as u16, word_operation);
+        }
+        value as u16
     }
 
-    fn update_flags(&mut self, a: u16, b: u16) {
-        let result = b.overflowing_sub(a).0;
+    // Multiplies AL or AX by `src` into AX or DX:AX. CF and OF are set when
+    // the upper half of the product is significant; the other flags are
+    // undefined and keep their values.
+    fn multiply(&mut self, mnemonic: &Mnemonic, src: u16, word_operation: bool) {
+        let signed = *mnemonic == Mnemonic::IMUL;
+        let overflow = if word_operation {
+            let a = self.ax.0;
+            let product = if signed {
+                (i32::from(a as i16) * i32::from(src as i16)) as u32
+            } else {
+                u32::from(a) * u32::from(src)
+            };
+            self.set_register(&RegisterName::AX, product as u16);
+            self.set_register(&RegisterName::DX, (product >> 16) as u16);
+            if signed {
+                product as i32 != i32::from(product as i16)
+            } else {
+                product > 0xFFFF
+            }
+        } else {
+            let a = self.get_register(&RegisterName::AL);
+            let product = if signed {
+                (i16::from(a as i8) * i16::from(src as i8)) as u16
+            } else {
+                a * (src & 0xFF)
+            };
+            self.set_register(&RegisterName::AX, product);
+            if signed {
+                product as i16 != i16::from(product as i8)
+            } else {
+                product > 0xFF
+            }
+        };
+        self.flags.cf = overflow;
+        self.flags.of = overflow;
+    }
 
-        let new_flags = Flags {
-            sf: (result & 0x8000) != 0,
-            zf: result == 0,
+    // Divides AX or DX:AX by `src`, leaving the quotient in AL or AX and the
+    // remainder in AH or DX. Returns false, changing nothing, on a divide
+    // error. The 8086 rejects the most negative quotient of a signed divide.
+    // All flags are undefined and keep their values.
+    fn divide(&mut self, mnemonic: &Mnemonic, src: u16, word_operation: bool) -> bool {
+        let signed = *mnemonic == Mnemonic::IDIV;
+        let (dividend, divisor, limit) = if word_operation {
+            let dividend = u32::from(self.dx.0) << 16 | u32::from(self.ax.0);
+            if signed {
+                (i64::from(dividend as i32), i64::from(src as i16), 0x7FFF)
+            } else {
+                (i64::from(dividend), i64::from(src), 0xFFFF)
+            }
+        } else if signed {
+            (
+                i64::from(self.ax.0 as i16),
+                i64::from(src as u8 as i8),
+                0x7F,
+            )
+        } else {
+            (i64::from(self.ax.0), i64::from(src & 0xFF), 0xFF)
         };
+        if divisor == 0 {
+            return false;
+        }
+        let quotient = dividend / divisor;
+        let remainder = dividend % divisor;
+        if quotient > limit || (signed && quotient < -limit) {
+            return false;
+        }
+        if word_operation {
+            self.set_register(&RegisterName::AX, quotient as u16);
+            self.set_register(&RegisterName::DX, remainder as u16);
+        } else {
+            self.set_register(&RegisterName::AL, quotient as u16);
+            self.set_register(&RegisterName::AH, remainder as u16);
+        }
+        true
+    }
+
+    // AAA and AAS correct AX after adding or subtracting two unpacked BCD
+    // digits in AL, carrying into AH. OF, SF, ZF and PF are undefined and keep
+    // their values.
+    fn ascii_adjust(&mut self, mnemonic: &Mnemonic) {
+        let mut value = self.ax.0;
+        let adjust = value & 0x0F > 9 || self.flags.af;
+        if adjust {
+            value = if *mnemonic == Mnemonic::AAA {
+                (value & 0xFF00).wrapping_add(0x100) | value.wrapping_add(6) & 0xFF
+            } else {
+                (value & 0xFF00).wrapping_sub(0x100) | value.wrapping_sub(6) & 0xFF
+            };
+        }
+        self.flags.af = adjust;
+        self.flags.cf = adjust;
+        self.set_register(&RegisterName::AX, value & 0xFF0F);
+    }
+
+    // AAM splits AL into base `base` digits in AH and AL; AAD joins them
+    // back into AL. Returns false on the divide error AAM raises for a zero
+    // base. OF, AF and CF are undefined and keep their values.
+    fn ascii_adjust_base(&mut self, mnemonic: &Mnemonic, base: u16) -> bool {
+        let (high, low) = (
+            self.get_register(&RegisterName::AH),
+            self.get_register(&RegisterName::AL),
+        );
+        let value = if *mnemonic == Mnemonic::AAM {
+            let Some(quotient) = low.checked_div(base) else {
+                return false;
+            };
+            (quotient << 8) | (low % base)
+        } else {
+            high.wrapping_mul(base).wrapping_add(low) & 0xFF
+        };
+        self.set_register(&RegisterName::AX, value);
+        self.result_flags(value & 0xFF, false);
+        true
+    }
+
+    // One MOVS, CMPS, SCAS, LODS or STOS, or the whole run of them under a REP
+    // prefix. SI, DI and CX move silently and are traced once at the end.
+    fn string_operation(
+        &mut self,
+        instruction: &Instruction,
+        mnemonic: &Mnemonic,
+        word_operation: bool,
+        memory: &mut Memory,
+    ) {
+        let source_segment = self.get_register(
+            instruction
+                .segment_override
+                .as_ref()
+                .unwrap_or(&RegisterName::DS),
+        );
+        let accumulator = if word_operation {
+            RegisterName::AX
+        } else {
+            RegisterName::AL
+        };
+        let step: u16 = if word_operation { 2 } else { 1 };
+        let step = if self.flags.df {
+            step.wrapping_neg()
+        } else {
+            step
+        };
+        let original = [
+            (RegisterName::SI, self.si.0),
+            (RegisterName::DI, self.di.0),
+            (RegisterName::CX, self.cx.0),
+        ];
+
+        loop {
+            if instruction.repeat.is_some() && self.cx.0 == 0 {
+                break;
+            }
+            let (source, destination) = (self.si.0, self.di.0);
+            match mnemonic {
+                Mnemonic::MOVS => {
+                    let value = memory.read(source_segment, source, word_operation);
+                    self.write_memory(memory, self.es.0, destination, value, word_operation);
+                }
+                Mnemonic::CMPS => {
+                    let a = memory.read(source_segment, source, word_operation);
+                    let b = memory.read(self.es.0, destination, word_operation);
+                    self.arithmetic(&Mnemonic::CMP, a, b, word_operation);
+                }
+                Mnemonic::SCAS => {
+                    let a = self.get_register(&accumulator);
+                    let b = memory.read(self.es.0, destination, word_operation);
+                    self.arithmetic(&Mnemonic::CMP, a, b, word_operation);
+                }
+                Mnemonic::LODS => {
+                    let value = memory.read(source_segment, source, word_operation);
+                    self.set_register(&accumulator, value);
+                }
+                _ => {
+                    let value = self.get_register(&accumulator);
+                    self.write_memory(memory, self.es.0, destination, value, word_operation);
+                }
+            }
+            if matches!(mnemonic, Mnemonic::MOVS | Mnemonic::CMPS | Mnemonic::LODS) {
+                self.si.0 = source.wrapping_add(step);
+            }
+            if !matches!(mnemonic, Mnemonic::LODS) {
+                self.di.0 = destination.wrapping_add(step);
+            }
+
+            let Some(repeat) = instruction.repeat else {
+                break;
+            };
+            self.cx.0 = self.cx.0.wrapping_sub(1);
+            if matches!(mnemonic, Mnemonic::CMPS | Mnemonic::SCAS)
+                && self.flags.zf != (repeat == Repeat::Rep)
+            {
+                break;
+            }
+        }
 
-        if self.flags != new_flags {
-            self.flags = new_flags;
+        for (name, old) in original {
+            let new = self.get_register(&name);
+            if new != old {
+                self.trace.registers.push(RegisterChange {
+                    register: name,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+
+    // DAA and DAS correct AL after adding or subtracting two packed BCD
+    // bytes. OF is undefined and keeps its value.
+    fn decimal_adjust(&mut self, mnemonic: &Mnemonic) {
+        let original = self.get_register(&RegisterName::AL) as u8;
+        let carry = self.flags.cf;
+        let subtract = *mnemonic == Mnemonic::DAS;
+        let mut value = original;
+        let adjust = |value: u8, amount: u8| {
+            if subtract {
+                value.overflowing_sub(amount)
+            } else {
+                value.overflowing_add(amount)
+            }
+        };
+        // Only a carry or borrow out of the first correction matters.
+        let mut carry_out = false;
+        if original & 0x0F > 9 || self.flags.af {
+            (value, carry_out) = adjust(value, 0x06);
+            self.flags.af = true;
+        } else {
+            self.flags.af = false;
+        }
+        if original > 0x99 || carry {
+            (value, _) = adjust(value, 0x60);
+            carry_out = true;
         }
+        self.flags.cf = carry_out;
+        self.set_register(&RegisterName::AL, u16::from(value));
+        self.result_flags(u16::from(value), false);
     }
 }
 
 impl std::fmt::Display for CPU {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
-        s.push_str(&format!("ax: {:#06x} ({})\n", self.ax.1, self.ax.1));
-        s.push_str(&format!("bx: {:#06x} ({})\n", self.bx.1, self.bx.1));
-        s.push_str(&format!("cx: {:#06x} ({})\n", self.cx.1, self.cx.1));
-        s.push_str(&format!("dx: {:#06x} ({})\n", self.dx.1, self.dx.1));
-        s.push_str(&format!("sp: {:#06x} ({})\n", self.sp.1, self.sp.1));
-        s.push_str(&format!("bp: {:#06x} ({})\n", self.bp.1, self.bp.1));
-        s.push_str(&format!("si: {:#06x} ({})\n", self.si.1, self.si.1));
-        s.push_str(&format!("di: {:#06x} ({})\n", self.di.1, self.di.1));
+        s.push_str(&format!("ax: {:#06x} ({})\n", self.ax.0, self.ax.0));
+        s.push_str(&format!("bx: {:#06x} ({})\n", self.bx.0, self.bx.0));
+        s.push_str(&format!("cx: {:#06x} ({})\n", self.cx.0, self.cx.0));
+        s.push_str(&format!("dx: {:#06x} ({})\n", self.dx.0, self.dx.0));
+        s.push_str(&format!("sp: {:#06x} ({})\n", self.sp.0, self.sp.0));
+        s.push_str(&format!("bp: {:#06x} ({})\n", self.bp.0, self.bp.0));
+        s.push_str(&format!("si: {:#06x} ({})\n", self.si.0, self.si.0));
+        s.push_str(&format!("di: {:#06x} ({})\n", self.di.0, self.di.0));
+        s.push_str(&format!("es: {:#06x} ({})\n", self.es.0, self.es.0));
+        s.push_str(&format!("cs: {:#06x} ({})\n", self.cs.0, self.cs.0));
+        s.push_str(&format!("ss: {:#06x} ({})\n", self.ss.0, self.ss.0));
+        s.push_str(&format!("ds: {:#06x} ({})\n", self.ds.0, self.ds.0));
         s.push_str(&format!("ip: {:#06x} ({})\n", self.ip, self.ip));
         s.push_str(&format!("flags: {}", self.flags));
         write!(f, "{}", s)
     }
 }
+
+fn write_psp(memory: &mut Memory, segment: u16, end_segment: u16, command_tail: &[u8]) {
+    // INT 20h, the classic way for a program to return to DOS.
+    memory.load(segment, 0, &[0xCD, 0x20]);
+    // Segment just past the memory available to the program.
+    memory.write(segment, 2, end_segment, true);
+    let command_tail = &command_tail[..command_tail.len().min(126)];
+    memory.write(segment, 0x80, command_tail.len() as u16, false);
+    memory.load(segment, 0x81, command_tail);
+    memory.load(segment, 0x81 + command_tail.len() as u16, b"\r");
+}
+
+// A jump to the instruction right after it still goes there on purpose.
+fn unconditional_jump(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.category(),
+        decode::InstructionCategory::Jump(Mnemonic::JMP, _)
+            | decode::InstructionCategory::NearJump(_, _)
+            | decode::InstructionCategory::FarJump(_, _, _)
+            | decode::InstructionCategory::IndirectFar(_, _)
+            | decode::InstructionCategory::SingleOperand(Mnemonic::JMP | Mnemonic::CALL, _, _)
+    )
+}
+
+// The two-operand operations `CPU::arithmetic` implements.
+fn alu_operation(mnemonic: &Mnemonic) -> bool {
+    matches!(
+        mnemonic,
+        Mnemonic::ADD
+            | Mnemonic::ADC
+            | Mnemonic::SUB
+            | Mnemonic::SBB
+            | Mnemonic::CMP
+            | Mnemonic::AND
+            | Mnemonic::TEST
+            | Mnemonic::OR
+            | Mnemonic::XOR
+    )
+}
+
+fn default_segment(base: &RegisterName) -> RegisterName {
+    match base {
+        RegisterName::BP => RegisterName::SS,
+        _ => RegisterName::DS,
+    }
+}
@@ -0,0 +1,312 @@
+use std::io::{self, Write};
+
+use crate::decode::RegisterName;
+use crate::simulate::{Flags, CPU};
+
+// Everything one executed instruction did, as reported to a `TraceSink`.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub cs: u16,
+    pub ip: u16,
+    pub next_ip: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: String,
+    pub clocks: TraceClocks,
+    // In the order the changes happened; a register can appear more than once.
+    pub registers: Vec<RegisterChange>,
+    pub memory: Vec<MemoryWrite>,
+    pub flags_before: Flags,
+    pub flags_after: Flags,
+    pub prefetch: Option<PrefetchClocks>,
+    pub exit_code: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TraceClocks {
+    pub base: u32,
+    pub effective_address: u16,
+    pub penalty: u32,
+    pub total: u32,
+    pub cycle_count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct RegisterChange {
+    pub register: RegisterName,
+    pub old: u16,
+    pub new: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryWrite {
+    pub segment: u16,
+    pub offset: u16,
+    pub value: u16,
+    pub word: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct PrefetchClocks {
+    pub elapsed: u64,
+    pub clock: u64,
+}
+
+// A failed write stops the simulation with `SimulationError::Trace`.
+pub trait TraceSink {
+    fn instruction(&mut self, trace: &Trace) -> io::Result<()>;
+
+    fn finish(&mut self, _cpu: &CPU) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The human readable trace, one line per instruction, followed by the final
+// register state.
+pub struct TextTrace<W: Write> {
+    output: W,
+}
+
+impl<W: Write> TextTrace<W> {
+    pub fn new(output: W) -> TextTrace<W> {
+        TextTrace { output }
+    }
+}
+
+impl<W: Write> TraceSink for TextTrace<W> {
+    fn instruction(&mut self, trace: &Trace) -> io::Result<()> {
+        let clocks = &trace.clocks;
+        let mut breakdown = vec![clocks.base.to_string()];
+        if clocks.effective_address > 0 {
+            breakdown.push(format!("{}ea", clocks.effective_address));
+        }
+        if clocks.penalty > 0 {
+            breakdown.push(format!("{}p", clocks.penalty));
+        }
+
+        let mut line = format!("{} ;", trace.instruction);
+        if breakdown.len() > 1 {
+            line.push_str(&format!(
+                " Clocks: +{} = {} ({}) |",
+                clocks.total,
+                clocks.cycle_count,
+                breakdown.join(" + ")
+            ));
+        } else {
+            line.push_str(&format!(
+                " Clocks: +{} = {} |",
+                clocks.total, clocks.cycle_count
+            ));
+        }
+        for change in &trace.registers {
+            line.push_str(&format!(
+                " {}:{:#x}->{:#x}",
+                change.register, change.old, change.new
+            ));
+        }
+        if let Some(exit_code) = trace.exit_code {
+            line.push_str(&format!(" exit:{}", exit_code));
+        }
+        line.push_str(&format!(" ip:{:#x}->{:#x}", trace.ip, trace.next_ip));
+        if trace.flags_before != trace.flags_after {
+            line.push_str(&format!(
+                " flags:{}->{}",
+                trace.flags_before, trace.flags_after
+            ));
+        }
+        if let Some(prefetch) = &trace.prefetch {
+            line.push_str(&format!(
+                " | Prefetch: +{} = {}",
+                prefetch.elapsed, prefetch.clock
+            ));
+        }
+
+        writeln!(self.output, "{}", line)
+    }
+
+    fn finish(&mut self, cpu: &CPU) -> io::Result<()> {
+        let mut summary = format!("\nFinal registers:\n{}\n", cpu);
+        if let Some(clock) = cpu.prefetch_clocks() {
+            summary.push_str(&format!("prefetch model clocks: {}\n", clock));
+        }
+        if let Some(exit_code) = cpu.exit_code() {
+            summary.push_str(&format!("exit code: {}\n", exit_code));
+        }
+        write!(self.output, "{}", summary)
+    }
+}
+
+// One JSON object per instruction.
+pub struct JsonLinesTrace<W: Write> {
+    output: W,
+}
+
+impl<W: Write> JsonLinesTrace<W> {
+    pub fn new(output: W) -> JsonLinesTrace<W> {
+        JsonLinesTrace { output }
+    }
+}
+
+impl<W: Write> TraceSink for JsonLinesTrace<W> {
+    fn instruction(&mut self, trace: &Trace) -> io::Result<()> {
+        let clocks = &trace.clocks;
+        let registers = trace
+            .registers
+            .iter()
+            .map(|change| {
+                format!(
+                    "{{\"register\":\"{}\",\"old\":{},\"new\":{}}}",
+                    change.register, change.old, change.new
+                )
+            })
+            .collect::<Vec<String>>();
+        let memory = trace
+            .memory
+            .iter()
+            .map(|write| {
+                format!(
+                    "{{\"segment\":{},\"offset\":{},\"value\":{},\"word\":{}}}",
+                    write.segment, write.offset, write.value, write.word
+                )
+            })
+            .collect::<Vec<String>>();
+        let prefetch = match &trace.prefetch {
+            Some(prefetch) => format!(
+                "{{\"elapsed\":{},\"clock\":{}}}",
+                prefetch.elapsed, prefetch.clock
+            ),
+            None => "null".to_string(),
+        };
+        let exit_code = match trace.exit_code {
+            Some(exit_code) => exit_code.to_string(),
+            None => "null".to_string(),
+        };
+
+        writeln!(
+            self.output,
+            "{{\"cs\":{},\"ip\":{},\"bytes\":\"{}\",\"instruction\":{},\
+             \"clocks\":{{\"base\":{},\"effective_address\":{},\"penalty\":{},\"total\":{},\"cycle_count\":{}}},\
+             \"registers\":[{}],\"memory\":[{}],\"flags_before\":\"{}\",\"flags_after\":\"{}\",\
+             \"next_ip\":{},\"prefetch\":{},\"exit_code\":{}}}",
+            trace.cs,
+            trace.ip,
+            hex_bytes(&trace.bytes),
+            json_string(&trace.instruction),
+            clocks.base,
+            clocks.effective_address,
+            clocks.penalty,
+            clocks.total,
+            clocks.cycle_count,
+            registers.join(","),
+            memory.join(","),
+            trace.flags_before,
+            trace.flags_after,
+            trace.next_ip,
+            prefetch,
+            exit_code
+        )
+    }
+}
+
+// A header row followed by one row per instruction. Register changes and
+// memory writes are space separated within their columns.
+pub struct CsvTrace<W: Write> {
+    output: W,
+    header_written: bool,
+}
+
+impl<W: Write> CsvTrace<W> {
+    pub fn new(output: W) -> CsvTrace<W> {
+        CsvTrace {
+            output,
+            header_written: false,
+        }
+    }
+}
+
+impl<W: Write> TraceSink for CsvTrace<W> {
+    fn instruction(&mut self, trace: &Trace) -> io::Result<()> {
+        if !self.header_written {
+            writeln!(
+                self.output,
+                "cs,ip,bytes,instruction,clocks,cycle_count,registers,memory,\
+                 flags_before,flags_after,next_ip,prefetch_clock,exit_code"
+            )?;
+            self.header_written = true;
+        }
+
+        let registers = trace
+            .registers
+            .iter()
+            .map(|change| format!("{}:{:#x}->{:#x}", change.register, change.old, change.new))
+            .collect::<Vec<String>>();
+        let memory = trace
+            .memory
+            .iter()
+            .map(|write| {
+                if write.word {
+                    format!(
+                        "{:04x}:{:04x}={:#06x}",
+                        write.segment, write.offset, write.value
+                    )
+                } else {
+                    format!(
+                        "{:04x}:{:04x}={:#04x}",
+                        write.segment, write.offset, write.value
+                    )
+                }
+            })
+            .collect::<Vec<String>>();
+        let fields = [
+            trace.cs.to_string(),
+            trace.ip.to_string(),
+            hex_bytes(&trace.bytes),
+            trace.instruction.clone(),
+            trace.clocks.total.to_string(),
+            trace.clocks.cycle_count.to_string(),
+            registers.join(" "),
+            memory.join(" "),
+            trace.flags_before.to_string(),
+            trace.flags_after.to_string(),
+            trace.next_ip.to_string(),
+            trace
+                .prefetch
+                .as_ref()
+                .map_or(String::new(), |prefetch| prefetch.clock.to_string()),
+            trace
+                .exit_code
+                .map_or(String::new(), |exit_code| exit_code.to_string()),
+        ];
+        let row = fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<String>>();
+
+        writeln!(self.output, "{}", row.join(","))
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
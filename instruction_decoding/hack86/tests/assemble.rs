@@ -0,0 +1,26 @@
+// Checks that the assembler picks the same encodings as NASM.
+
+use hack86::assemble;
+
+#[test]
+fn short_forward_jump_past_0x80() {
+    let source = format!(
+        "bits 16\n{}jmp target\nnop\ntarget:\nhlt\n",
+        "mov ax, bx\n".repeat(70)
+    );
+    let bytes = assemble(&source).expect("failed to assemble");
+    assert_eq!(bytes.len(), 140 + 4);
+    assert_eq!(bytes[140..], [0xeb, 0x01, 0x90, 0xf4]);
+}
+
+#[test]
+fn forward_jump_widened_only_when_needed() {
+    let source = format!(
+        "bits 16\njmp short_target\n{}short_target:\njmp near_target\n{}near_target:\nhlt\n",
+        "nop\n".repeat(127),
+        "nop\n".repeat(128)
+    );
+    let bytes = assemble(&source).expect("failed to assemble");
+    assert_eq!(bytes[..2], [0xeb, 0x7f]);
+    assert_eq!(bytes[129..132], [0xe9, 0x80, 0x00]);
+}
@@ -0,0 +1,178 @@
+// Feeds the decoder pseudo-random byte streams. Every stream has to either
+// decode or fail with a DecodeError, and whatever decodes has to survive a
+// round trip through the encoder. Set HACK86_FUZZ_SEED to replay a failure.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use hack86::decode::{decode_instruction, DecodeError, Instruction};
+use hack86::encode::encode_instruction;
+
+const DEFAULT_SEED: u64 = 0x8086_8088_0186_0286;
+const RANDOM_STREAMS: usize = 200_000;
+// Prefixes and the longest operation (opcode, ModRM, disp16, imm16).
+const MAX_STREAM_LENGTH: usize = 12;
+
+// xorshift64*: no dependencies and the same streams on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn bytes(&mut self, length: usize) -> Vec<u8> {
+        (0..length).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn seed() -> u64 {
+    match std::env::var("HACK86_FUZZ_SEED") {
+        Ok(seed) => seed.parse().expect("HACK86_FUZZ_SEED must be a number"),
+        Err(_) => DEFAULT_SEED,
+    }
+}
+
+#[test]
+fn random_streams() {
+    let seed = seed();
+    let mut rng = Rng::new(seed);
+    for _ in 0..RANDOM_STREAMS {
+        let length = 1 + (rng.next() as usize) % MAX_STREAM_LENGTH;
+        let bytes = rng.bytes(length);
+        if let Err(failure) = check(&bytes) {
+            panic!("seed {}: {:02x?}: {}", seed, bytes, failure);
+        }
+    }
+}
+
+// Covers every opcode with every ModRM byte, followed by random operands.
+#[test]
+fn opcode_and_modrm_pairs() {
+    let seed = seed();
+    let mut rng = Rng::new(seed);
+    for opcode in 0..=u8::MAX {
+        for modrm in 0..=u8::MAX {
+            let mut bytes = vec![opcode, modrm];
+            bytes.extend(rng.bytes(4));
+            if let Err(failure) = check(&bytes) {
+                panic!("seed {}: {:02x?}: {}", seed, bytes, failure);
+            }
+        }
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Result<Instruction, DecodeError>, String> {
+    panic::catch_unwind(AssertUnwindSafe(|| decode_instruction(bytes)))
+        .map_err(|_| "decoder panicked".to_string())
+}
+
+fn check(bytes: &[u8]) -> Result<(), String> {
+    let instruction = match decode(bytes)? {
+        Ok(instruction) => instruction,
+        Err(DecodeError::Truncated { needed, available }) => {
+            if available != bytes.len() || needed <= available {
+                return Err(format!(
+                    "truncated with needed {} and available {}",
+                    needed, available
+                ));
+            }
+            return Ok(());
+        }
+        Err(
+            DecodeError::UnknownOpcode { offset, .. }
+            | DecodeError::InvalidModRm { offset, .. }
+            | DecodeError::TooLong { offset },
+        ) => {
+            if offset >= bytes.len() {
+                return Err(format!("error offset {} is past the stream", offset));
+            }
+            return Ok(());
+        }
+        Err(_) => return Ok(()),
+    };
+
+    let length = usize::from(instruction.length());
+    if length == 0 || length > bytes.len() {
+        return Err(format!("decoded length {}", length));
+    }
+    let text = instruction.to_string();
+
+    // The instruction must not depend on anything past its own length...
+    match decode(&bytes[..length])? {
+        Ok(exact) if usize::from(exact.length()) == length && exact.to_string() == text => {}
+        Ok(exact) => {
+            return Err(format!(
+                "decodes as `{}` ({} bytes) but alone as `{}` ({} bytes)",
+                text,
+                length,
+                exact,
+                exact.length()
+            ))
+        }
+        Err(error) => return Err(format!("decodes as `{}` but alone: {}", text, error)),
+    }
+    // ...and must need all of it.
+    match decode(&bytes[..length - 1])? {
+        Err(DecodeError::Truncated { .. }) => {}
+        other => {
+            return Err(format!(
+                "`{}` without its last byte is {}",
+                text,
+                match other {
+                    Ok(shorter) => format!("`{}`", shorter),
+                    Err(error) => error.to_string(),
+                }
+            ))
+        }
+    }
+
+    // The encoder may pick a different encoding, but it has to mean the same.
+    let encoded = panic::catch_unwind(AssertUnwindSafe(|| encode_instruction(&instruction)))
+        .map_err(|_| format!("encoder panicked on `{}`", text))?
+        .map_err(|error| format!("`{}` does not encode: {}", text, error))?;
+    match decode(&encoded)? {
+        Ok(round_trip)
+            if usize::from(round_trip.length()) == encoded.len()
+                && canonical(&round_trip.to_string()) == canonical(&text) =>
+        {
+            Ok(())
+        }
+        Ok(round_trip) => Err(format!(
+            "`{}` encodes as {:02x?}, which decodes as `{}` ({} bytes)",
+            text,
+            encoded,
+            round_trip,
+            round_trip.length()
+        )),
+        Err(error) => Err(format!("`{}` encodes as {:02x?}: {}", text, encoded, error)),
+    }
+}
+
+// The encoder always picks the short accumulator form of xchg, which the
+// decoder prints with the operands the other way round (or as nop).
+fn canonical(text: &str) -> String {
+    if let Some(prefixes) = text.strip_suffix("nop") {
+        return format!("{}xchg ax, ax", prefixes);
+    }
+    match text.split_once("xchg ") {
+        Some((prefixes, operands)) => match operands.split_once(", ") {
+            Some((first, second)) => {
+                format!(
+                    "{}xchg {}, {}",
+                    prefixes,
+                    first.min(second),
+                    first.max(second)
+                )
+            }
+            None => text.to_string(),
+        },
+        None => text.to_string(),
+    }
+}
@@ -0,0 +1,141 @@
+// Drives the devices through the bus and through simulated programs.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use hack86::bus::{Bus, Device};
+use hack86::decode::RegisterName;
+use hack86::devices::{SerialPort, TextVideo, COM1};
+use hack86::trace::TextTrace;
+use hack86::{assemble, CpuModel, Hack86};
+
+// Serial output the test can still read after the port has been moved onto
+// the bus.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn port_dispatch() {
+    let output = Output::default();
+    let mut bus = Bus::new();
+    bus.attach(Box::new(SerialPort::new(COM1, output.clone())));
+
+    bus.output(COM1, b'A');
+    // Line status: always ready to transmit.
+    assert_eq!(bus.input(COM1 + 5), 0x60);
+    // With the divisor latch open, the transmit port holds the divisor.
+    bus.output(COM1 + 3, 0x80);
+    bus.output(COM1, 0x0c);
+    assert_eq!(bus.input(COM1), 0x0c);
+    bus.output(COM1 + 3, 0x03);
+    bus.output(COM1, b'B');
+    assert_eq!(bus.input(COM1 + 3), 0x03);
+
+    // Nothing claims the ports on either side.
+    bus.output(COM1 - 1, b'x');
+    bus.output(COM1 + 8, b'x');
+    assert_eq!(bus.input(COM1 + 8), 0xff);
+    assert_eq!(*output.0.borrow(), b"AB");
+}
+
+#[test]
+fn serial_port_at_top_of_io_space() {
+    let output = Output::default();
+    let port = SerialPort::new(0xfffc, output.clone());
+    assert_eq!(port.ports(), Some(0xfffc..=0xffff));
+
+    let mut bus = Bus::new();
+    bus.attach(Box::new(port));
+    bus.output(0xfffc, b'A');
+    assert_eq!(bus.input(0xffff), 0);
+    assert_eq!(*output.0.borrow(), b"A");
+}
+
+fn run(source: &str, output: &Output) -> Hack86 {
+    let program = assemble(&format!("bits 16\n{}", source)).expect("failed to assemble");
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.attach_device(Box::new(TextVideo::new()));
+    computer.attach_device(Box::new(SerialPort::new(COM1, output.clone())));
+    computer.simulate().expect("simulation failed");
+    computer
+}
+
+#[test]
+fn serial_transmit() {
+    let output = Output::default();
+    run(
+        "
+        mov dx, 0x3f8
+        mov al, 'H'
+        out dx, al
+        mov al, 'i'
+        out dx, al
+    ",
+        &output,
+    );
+    assert_eq!(*output.0.borrow(), b"Hi");
+}
+
+#[test]
+fn text_video() {
+    let computer = run(
+        "
+        mov ax, 0xb800
+        mov es, ax
+        mov byte es:[0], 'H'
+        mov byte es:[2], 'i'
+        mov byte es:[160], '!'
+        mov byte es:[162], 0x01
+    ",
+        &Output::default(),
+    );
+    let video = computer.device::<TextVideo>().expect("no video device");
+    assert_eq!(video.text(), "Hi\n!.");
+    // The writes went to the device, not to RAM underneath it.
+    assert_eq!(computer.memory()[0xb8000], 0);
+}
+
+struct ClosedPipe;
+
+impl Write for ClosedPipe {
+    fn write(&mut self, _bytes: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn serial_write_failure() {
+    let program =
+        assemble("bits 16\nmov dx, 0x3f8\nmov al, 'A'\nout dx, al\nout dx, al\nmov bx, 1")
+            .expect("failed to assemble");
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.attach_device(Box::new(SerialPort::new(COM1, ClosedPipe)));
+    // The program carries on; the host finds the error on the port.
+    computer.simulate().expect("simulation failed");
+    assert_eq!(computer.cpu().get_register(&RegisterName::BX), 1);
+    let port = computer
+        .device::<SerialPort<ClosedPipe>>()
+        .expect("no serial port");
+    assert_eq!(
+        port.error().map(|error| error.kind()),
+        Some(std::io::ErrorKind::BrokenPipe)
+    );
+}
@@ -0,0 +1,286 @@
+// Runs every course listing in `asm` through the decoder and the simulator and
+// compares the output with the files in `tests/golden`. Set UPDATE_GOLDEN=1 to
+// rewrite the golden files from the current output instead.
+//
+// The golden files only catch changes in behaviour, so they are backed by the
+// hand-written files in `tests/reference`, which UPDATE_GOLDEN never touches:
+// the machine code of every listing, encoded by hand from the Intel manual the
+// way NASM encodes it, and the listing 0056 trace with its clocks worked out
+// from the manual's timing tables.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// These listings loop forever when simulated; they only exercise the decoder.
+const DECODE_ONLY: [&str; 1] = ["listing_0041_add_sub_cmp_jnz"];
+
+// These run for tens of thousands of instructions; the memory and prefetch
+// goldens already hold their final state, so their traces are not kept.
+const UNTRACED: [&str; 1] = ["listing_0054_draw_rectangle"];
+
+// Listings whose trace is checked against `tests/reference` instead.
+const REFERENCE_TRACES: [&str; 1] = ["listing_0056_estimating_cycles"];
+
+struct Listing {
+    name: String,
+    directory: PathBuf,
+}
+
+#[test]
+fn disassembly() {
+    let mut failures = Vec::new();
+    for listing in listings("disassembly") {
+        let output = hack86(&listing.directory, &[&listing.name]);
+        check(&format!("{}.disasm", listing.name), &output, &mut failures);
+    }
+    report(failures);
+}
+
+// The disassembly has to be source that assembles back to the same bytes.
+#[test]
+fn round_trip() {
+    let mut failures = Vec::new();
+    let mut listings = listings("round_trip");
+    // None of the listings has a near jump that could have been short.
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("round_trip")
+        .join("jumps");
+    std::fs::create_dir_all(&directory).expect("failed to create scratch directory");
+    std::fs::write(
+        directory.join("jumps.asm"),
+        "bits 16\njmp near forward\nnop\nforward:\njmp backward\nbackward:\njmp near backward\n",
+    )
+    .expect("failed to write jumps.asm");
+    hack86(&directory, &["-a", "jumps.asm"]);
+    listings.push(Listing {
+        name: "jumps".to_string(),
+        directory,
+    });
+    for listing in listings {
+        let binary = std::fs::read(listing.directory.join(&listing.name))
+            .expect("failed to read assembled listing");
+        let disassembly = hack86(&listing.directory, &[&listing.name]);
+        match hack86::encode::assemble(&disassembly) {
+            Ok(bytes) if bytes == binary => {}
+            Ok(bytes) => {
+                let offset = (0..bytes.len().max(binary.len()))
+                    .find(|&offset| bytes.get(offset) != binary.get(offset))
+                    .unwrap_or(0);
+                failures.push(format!(
+                    "{} reassembles differently at offset {:#x}",
+                    listing.name, offset
+                ));
+            }
+            Err(error) => failures.push(format!(
+                "{} disassembly does not assemble: {}",
+                listing.name, error
+            )),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn assembly() {
+    let mut failures = Vec::new();
+    for listing in listings("assembly") {
+        let binary = std::fs::read(listing.directory.join(&listing.name))
+            .expect("failed to read assembled listing");
+        let reference = reference(&format!("{}.hex", listing.name));
+        let expected = reference
+            .lines()
+            .flat_map(|line| line.split(';').next().unwrap_or("").split_whitespace())
+            .map(|byte| u8::from_str_radix(byte, 16).expect("bad byte in reference"))
+            .collect::<Vec<u8>>();
+        if let Some(offset) = (0..expected.len().max(binary.len()))
+            .find(|&offset| expected.get(offset) != binary.get(offset))
+        {
+            let byte = |bytes: &[u8]| {
+                bytes
+                    .get(offset)
+                    .map_or("<end of file>".to_string(), |byte| format!("{:02x}", byte))
+            };
+            failures.push(format!(
+                "{} assembles differently from the reference at offset {:#x}:\n  expected: {}\n  actual:   {}",
+                listing.name,
+                offset,
+                byte(&expected),
+                byte(&binary)
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn simulation() {
+    let mut failures = Vec::new();
+    for listing in listings("simulation") {
+        if DECODE_ONLY.contains(&listing.name.as_str()) {
+            continue;
+        }
+
+        let output = hack86(&listing.directory, &["-s", "-d", &listing.name]);
+        let trace = format!("{}.trace", listing.name);
+        if REFERENCE_TRACES.contains(&listing.name.as_str()) {
+            let expected = reference(&trace);
+            if expected != output {
+                failures.push(format!(
+                    "{} differs from the reference:\n{}",
+                    trace,
+                    first_difference(&expected, &output)
+                ));
+            }
+        } else if !UNTRACED.contains(&listing.name.as_str()) {
+            check(&trace, &output, &mut failures);
+        }
+
+        let memory = std::fs::read(listing.directory.join("hack86_memory.data"))
+            .expect("failed to read memory dump");
+        assert_eq!(memory.len(), 0x10000, "{} memory dump size", listing.name);
+        check(
+            &format!("{}.memory", listing.name),
+            &memory_dump(&memory),
+            &mut failures,
+        );
+
+        // The per-instruction clocks are already covered above; keep just the
+        // prefetch model's totals.
+        let output = hack86(&listing.directory, &["-s", "-p", &listing.name]);
+        let summary = output
+            .find("\nFinal registers:")
+            .map_or(output.as_str(), |start| &output[start + 1..]);
+        check(
+            &format!("{}.prefetch", listing.name),
+            summary,
+            &mut failures,
+        );
+    }
+    report(failures);
+}
+
+// Assembles each listing with hack86 itself into a scratch directory of its
+// own per test, so parallel runs don't share binaries or memory dumps.
+fn listings(test: &str) -> Vec<Listing> {
+    let source_directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("asm");
+    let mut sources = std::fs::read_dir(&source_directory)
+        .expect("failed to read asm directory")
+        .map(|entry| entry.expect("failed to read asm directory").path())
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == "asm")
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("listing_"))
+        })
+        .collect::<Vec<PathBuf>>();
+    sources.sort();
+
+    sources
+        .iter()
+        .map(|source| {
+            let name = source
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .expect("listing name is not valid UTF-8")
+                .to_string();
+            let directory = Path::new(env!("CARGO_TARGET_TMPDIR"))
+                .join(test)
+                .join(&name);
+            std::fs::create_dir_all(&directory).expect("failed to create scratch directory");
+            let assembly = format!("{}.asm", name);
+            std::fs::copy(source, directory.join(&assembly)).expect("failed to copy listing");
+            hack86(&directory, &["-a", &assembly]);
+            Listing { name, directory }
+        })
+        .collect()
+}
+
+fn hack86(directory: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_hack86"))
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .expect("failed to run hack86");
+    assert!(
+        output.status.success(),
+        "hack86 {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).expect("hack86 output is not valid UTF-8")
+}
+
+// Only the 16 byte rows holding something other than zeros.
+fn memory_dump(memory: &[u8]) -> String {
+    let mut dump = String::new();
+    for (row, bytes) in memory.chunks(16).enumerate() {
+        if bytes.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+        let hex = bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<String>>();
+        dump.push_str(&format!("{:05x}: {}\n", row * 16, hex.join(" ")));
+    }
+    dump
+}
+
+fn reference(file_name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("reference")
+        .join(file_name);
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("failed to read {}: {}", path.display(), error))
+}
+
+fn check(file_name: &str, actual: &str, failures: &mut Vec<String>) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(file_name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).expect("failed to create golden directory");
+        std::fs::write(&path, actual).expect("failed to write golden file");
+        return;
+    }
+
+    match std::fs::read_to_string(&path) {
+        Ok(expected) if expected == actual => {}
+        Ok(expected) => failures.push(format!(
+            "{} differs from the golden file:\n{}",
+            file_name,
+            first_difference(&expected, actual)
+        )),
+        Err(_) => failures.push(format!("{} has no golden file", file_name)),
+    }
+}
+
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => line += 1,
+            (expected, actual) => {
+                return format!(
+                    "  line {}\n  expected: {}\n  actual:   {}",
+                    line,
+                    expected.unwrap_or("<end of file>"),
+                    actual.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+}
+
+fn report(failures: Vec<String>) {
+    assert!(
+        failures.is_empty(),
+        "{}\n\nrun with UPDATE_GOLDEN=1 to accept the new output",
+        failures.join("\n\n")
+    );
+}
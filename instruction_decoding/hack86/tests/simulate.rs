@@ -0,0 +1,499 @@
+// Runs small assembled programs through the simulator and checks the
+// registers and memory they leave behind.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use hack86::decode::RegisterName;
+use hack86::dos::DosServices;
+use hack86::memory::Memory;
+use hack86::simulate::{InterruptHandler, InterruptResult, SimulationError};
+use hack86::trace::{TextTrace, Trace, TraceSink};
+use hack86::{assemble, CpuModel, Hack86, CPU};
+
+fn run(source: &str) -> Hack86 {
+    let program = assemble(&format!("bits 16\n{}", source)).expect("failed to assemble");
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.simulate().expect("simulation failed");
+    computer
+}
+
+fn register(computer: &Hack86, name: RegisterName) -> u16 {
+    computer.cpu().get_register(&name)
+}
+
+fn flags(computer: &Hack86) -> String {
+    computer.cpu().flags().to_string()
+}
+
+#[test]
+fn exchange_and_load_address() {
+    let computer = run("
+        mov ax, 1
+        mov bx, 2
+        xchg ax, bx
+        mov cl, 3
+        mov ch, 4
+        xchg cl, ch
+        mov word [1000], 5
+        xchg [1000], bx
+        mov bp, 100
+        mov si, 20
+        lea di, [bp + si - 4]
+        mov word [2000], 0x1234
+        mov word [2002], 0x5678
+        lds dx, [2000]
+        mov word [2000], 0x9abc
+        mov word [2002], 0xdef0
+        les si, [2000]
+    ");
+    assert_eq!(register(&computer, RegisterName::AX), 2);
+    assert_eq!(register(&computer, RegisterName::BX), 5);
+    assert_eq!(register(&computer, RegisterName::CX), 0x0304);
+    assert_eq!(computer.memory()[1000], 1);
+    assert_eq!(register(&computer, RegisterName::DI), 116);
+    assert_eq!(register(&computer, RegisterName::DX), 0x1234);
+    assert_eq!(register(&computer, RegisterName::DS), 0x5678);
+    assert_eq!(register(&computer, RegisterName::SI), 0x9abc);
+    assert_eq!(register(&computer, RegisterName::ES), 0xdef0);
+}
+
+// Code the program writes past its own image runs when it jumps there; only
+// running on from the last instruction ends the simulation.
+#[test]
+fn code_written_past_the_image() {
+    let computer = run("
+        mov byte [end], 0xbb
+        mov word [end + 1], 0x1234
+        mov byte [end + 3], 0xf4
+        jmp end
+    end:
+    ");
+    assert_eq!(register(&computer, RegisterName::BX), 0x1234);
+    assert!(computer.cpu().halted());
+
+    let computer = run("mov ax, 1\nmov bx, 2");
+    assert_eq!(computer.instruction_count(), 2);
+}
+
+#[test]
+fn flag_instructions() {
+    assert_eq!(flags(&run("stc\nstd\nsti")), "CID");
+    assert_eq!(flags(&run("stc\ncmc\nstd\ncld\nsti\ncli")), "");
+}
+
+#[test]
+fn shift_flags() {
+    // add leaves AF set; shifts must not touch it.
+    let computer = run("mov al, 0x0f\nadd al, 1\nmov al, 0x81\nshl al, 1");
+    assert_eq!(register(&computer, RegisterName::AL), 0x02);
+    assert_eq!(flags(&computer), "CAO");
+
+    let computer = run("mov bx, 0x4000\nshl bx, 1");
+    assert_eq!(register(&computer, RegisterName::BX), 0x8000);
+    assert_eq!(flags(&computer), "PSO");
+
+    let computer = run("mov al, 0x81\nshr al, 1");
+    assert_eq!(register(&computer, RegisterName::AL), 0x40);
+    assert_eq!(flags(&computer), "CO");
+
+    let computer = run("mov al, 0x81\nsar al, 1");
+    assert_eq!(register(&computer, RegisterName::AL), 0xc0);
+    assert_eq!(flags(&computer), "CPS");
+
+    let computer = run("mov al, 0x81\nmov cl, 4\nrol al, cl");
+    assert_eq!(register(&computer, RegisterName::AL), 0x18);
+    assert_eq!(flags(&computer), "");
+
+    let computer = run("stc\nmov al, 1\nrcr al, 1");
+    assert_eq!(register(&computer, RegisterName::AL), 0x80);
+    assert_eq!(flags(&computer), "CO");
+
+    // A zero count changes nothing, flags included.
+    let computer = run("stc\nmov ax, 1\nmov cl, 0\nshl ax, cl");
+    assert_eq!(register(&computer, RegisterName::AX), 1);
+    assert_eq!(flags(&computer), "C");
+}
+
+#[test]
+fn multiply_flags() {
+    let computer = run("mov al, 16\nmov bl, 16\nmul bl");
+    assert_eq!(register(&computer, RegisterName::AX), 256);
+    assert_eq!(flags(&computer), "CO");
+
+    let computer = run("mov ax, 300\nmov bx, 300\nmul bx");
+    assert_eq!(register(&computer, RegisterName::DX), 0x0001);
+    assert_eq!(register(&computer, RegisterName::AX), 0x5f90);
+    assert_eq!(flags(&computer), "CO");
+
+    let computer = run("mov al, 0x80\nmov bl, 2\nimul bl");
+    assert_eq!(register(&computer, RegisterName::AX), 0xff00);
+    assert_eq!(flags(&computer), "CO");
+
+    // The product fits in AX once sign extended, and AF is left alone.
+    let computer = run("mov al, 0x0f\nadd al, 1\nmov ax, 0xffff\nmov bx, 2\nimul bx");
+    assert_eq!(register(&computer, RegisterName::DX), 0xffff);
+    assert_eq!(register(&computer, RegisterName::AX), 0xfffe);
+    assert_eq!(flags(&computer), "A");
+}
+
+#[test]
+fn divide() {
+    let computer = run("mov dx, 1\nmov ax, 0\nmov bx, 2\ndiv bx");
+    assert_eq!(register(&computer, RegisterName::AX), 0x8000);
+    assert_eq!(register(&computer, RegisterName::DX), 0);
+
+    let computer = run("mov ax, 0xfff9\nmov bl, 2\nidiv bl");
+    assert_eq!(register(&computer, RegisterName::AL), 0xfd);
+    assert_eq!(register(&computer, RegisterName::AH), 0xff);
+}
+
+#[test]
+fn decimal_adjust() {
+    let computer = run("mov al, 0x19\nadd al, 0x28\ndaa");
+    assert_eq!(register(&computer, RegisterName::AL), 0x47);
+    assert_eq!(flags(&computer), "PA");
+
+    let computer = run("mov al, 0x99\nadd al, 1\ndaa");
+    assert_eq!(register(&computer, RegisterName::AL), 0x00);
+    assert_eq!(flags(&computer), "CPAZ");
+
+    let computer = run("mov al, 0x47\nsub al, 0x28\ndas");
+    assert_eq!(register(&computer, RegisterName::AL), 0x19);
+    assert_eq!(flags(&computer), "A");
+}
+
+// A .COM program that walks through the string, flag, BCD and control
+// transfer instructions, storing what it computes at 0400h and halting.
+#[test]
+fn com_program() {
+    let program = assemble(
+        "
+        bits 16
+        org 100h
+        cld
+        clc
+        nop
+        wait
+        mov si, source
+        mov di, 0x400
+        movsb
+        mov cx, 3
+        mov ax, 0x0707
+        rep stosw
+        mov ax, 3
+        shl ax, 1
+        mov bx, 7
+        mul bx
+        mov bx, 5
+        div bx
+        mov [0x408], ax
+        mov [0x40a], dx
+        mov bx, near_target
+        jmp bx
+        hlt
+    near_target:
+        jmp far [far_pointer]
+        hlt
+    far_target:
+        mov al, 0x80
+        cbw
+        cwd
+        mov [0x40c], ax
+        mov [0x40e], dx
+        mov bx, table
+        mov al, 2
+        xlat
+        mov [0x410], al
+        mov al, 0x15
+        add al, 0x27
+        daa
+        mov [0x411], al
+        mov ax, 9
+        add al, 5
+        aaa
+        mov [0x412], ax
+        mov al, 47
+        aam
+        mov [0x414], ax
+        mov si, table
+        mov di, 0x416
+        mov cx, 3
+        rep movsb
+        mov si, table
+        mov di, 0x416
+        mov cx, 3
+        repe cmpsb
+        mov [0x419], cl
+        mov al, 20
+        mov di, table
+        mov cx, 3
+        repne scasb
+        mov [0x41a], cl
+        std
+        mov si, 0x41a
+        lodsb
+        mov [0x41b], si
+        cld
+        stc
+        lahf
+        and ah, 0xfe
+        sahf
+        hlt
+        mov ax, 0xdead
+    source:
+        db 'A'
+    table:
+        db 10, 20, 30
+    far_pointer:
+        dw far_target, 0x1000
+    ",
+    )
+    .expect("failed to assemble");
+    let mut computer = Hack86::load_com(&program, b"", CpuModel::Intel8086).unwrap();
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.simulate().expect("simulation failed");
+
+    assert!(computer.cpu().halted());
+    assert_ne!(register(&computer, RegisterName::AX), 0xdead);
+    assert!(!flags(&computer).contains('C'));
+    assert_eq!(
+        computer.read_memory(0x1000, 0x400, 0x1d),
+        [
+            b'A', 7, 7, 7, 7, 7, 7, 0, // movsb, rep stosw
+            8, 0, 2, 0, // 42 / 5
+            0x80, 0xff, 0xff, 0xff, // cbw, cwd
+            30,   // xlat
+            0x42, // daa
+            4, 1, // aaa
+            7, 4, // aam
+            10, 20, 30, // rep movsb
+            0,  // repe cmpsb ran to the end
+            1,  // repne scasb stopped on the second byte
+            0x19, 0x04, // std; lodsb
+        ]
+    );
+}
+
+// Collects the clocks charged for each instruction.
+struct ClockSink(Rc<RefCell<Vec<u32>>>);
+
+impl TraceSink for ClockSink {
+    fn instruction(&mut self, trace: &Trace) -> std::io::Result<()> {
+        self.0.borrow_mut().push(trace.clocks.total);
+        Ok(())
+    }
+}
+
+fn clocks(source: &str, model: CpuModel) -> Vec<u32> {
+    let program = assemble(&format!("bits 16\n{}", source)).expect("failed to assemble");
+    let mut computer = Hack86::new(program, model);
+    let clocks = Rc::new(RefCell::new(Vec::new()));
+    computer.set_trace_sink(Box::new(ClockSink(clocks.clone())));
+    computer.simulate().expect("simulation failed");
+    clocks.take()
+}
+
+#[test]
+fn stack_transfer_clocks() {
+    let program = |sp: u16| {
+        format!(
+            "mov sp, {}\npush ax\ncall function\npop ax\nhlt\nfunction:\nret",
+            sp
+        )
+    };
+    // mov, push, call, ret, pop, hlt
+    assert_eq!(
+        clocks(&program(0x100), CpuModel::Intel8086),
+        [4, 11, 19, 8, 8, 2]
+    );
+    // Each push and pop moves a word over the 8088's byte-wide bus.
+    assert_eq!(
+        clocks(&program(0x100), CpuModel::Intel8088),
+        [4, 15, 23, 12, 12, 2]
+    );
+    // The 8086 splits word transfers at odd addresses the same way.
+    assert_eq!(
+        clocks(&program(0x101), CpuModel::Intel8086),
+        [4, 15, 23, 12, 12, 2]
+    );
+}
+
+#[test]
+fn string_transfer_clocks() {
+    let program = "mov si, 0x100\nmov di, 0x201\nmovsw\nlodsw\nmov cx, 2\nrep stosw\nmovsb";
+    // mov, mov, movsw, lodsw, mov, rep stosw, movsb
+    assert_eq!(
+        clocks(program, CpuModel::Intel8086),
+        [4, 4, 22, 12, 4, 37, 18]
+    );
+    assert_eq!(
+        clocks(program, CpuModel::Intel8088),
+        [4, 4, 26, 16, 4, 37, 18]
+    );
+    // repne scasb finds the byte on the second of three and stops there.
+    assert_eq!(
+        clocks(
+            "mov di, 0x300\nmov byte [0x301], 7\nmov al, 7\nmov cx, 3\nrepne scasb",
+            CpuModel::Intel8086
+        ),
+        [4, 16, 4, 4, 9 + 15 * 2]
+    );
+    // repe cmpsw stops at the first difference, after two of four words, and
+    // only those two pay the 8088's penalty.
+    let program = "mov si, 0x300\nmov di, 0x400\nmov word [0x402], 1\nmov cx, 4\nrepe cmpsw";
+    assert_eq!(
+        clocks(program, CpuModel::Intel8086),
+        [4, 4, 16, 4, 9 + 22 * 2]
+    );
+    assert_eq!(
+        clocks(program, CpuModel::Intel8088),
+        [4, 4, 20, 4, 9 + 22 * 2 + 4 * 4]
+    );
+    // Long runs need more clocks than fit in 16 bits.
+    assert_eq!(
+        clocks("mov cx, 0xffff\nrep stosw", CpuModel::Intel8086),
+        [4, 9 + 10 * 0xffff]
+    );
+}
+
+fn run_com(source: &str) -> (Hack86, Result<(), SimulationError>) {
+    let program = assemble(&format!("bits 16\norg 100h\n{}", source)).expect("failed to assemble");
+    let mut computer = Hack86::load_com(&program, b"", CpuModel::Intel8086).unwrap();
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    let result = computer.simulate();
+    (computer, result)
+}
+
+#[test]
+fn unhandled_interrupts() {
+    let (computer, result) = run_com("mov ah, 0\nint 0x10");
+    assert!(matches!(
+        result,
+        Err(SimulationError::UnhandledInterrupt(0x10))
+    ));
+    // The CPU stays on the failing instruction.
+    assert_eq!(computer.cpu().ip(), 0x102);
+
+    let (_, result) = run_com("mov ax, 1\nmov bl, 0\ndiv bl");
+    assert!(matches!(
+        result,
+        Err(SimulationError::UnhandledInterrupt(0))
+    ));
+
+    // Once the program installs a handler, the interrupt goes through it.
+    let (computer, result) = run_com(
+        "
+        mov ax, 0
+        mov es, ax
+        mov word es:[0x40], handler
+        mov es:[0x42], cs
+        int 0x10
+        hlt
+    handler:
+        mov bx, 0x1234
+        iret
+    ",
+    );
+    assert!(result.is_ok());
+    assert_eq!(register(&computer, RegisterName::BX), 0x1234);
+}
+
+// Answers the DOS version query and leaves everything else to the program.
+struct DosVersion;
+
+impl InterruptHandler for DosVersion {
+    fn interrupt(&mut self, vector: u8, cpu: &mut CPU, _memory: &mut Memory) -> InterruptResult {
+        if vector == 0x21 && cpu.get_register(&RegisterName::AH) == 0x30 {
+            cpu.set_register(&RegisterName::AX, 0x0005);
+            InterruptResult::Handled
+        } else {
+            InterruptResult::Unhandled
+        }
+    }
+}
+
+#[test]
+fn interrupt_handler() {
+    let program = assemble("bits 16\nmov ah, 0x30\nint 0x21\nmov bx, ax").unwrap();
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.set_interrupt_handler(Box::new(DosVersion));
+    computer.enable_journal(16, 1 << 20);
+    computer.simulate().expect("simulation failed");
+    assert_eq!(register(&computer, RegisterName::AX), 0x0005);
+    assert_eq!(register(&computer, RegisterName::BX), 0x0005);
+
+    // What the handler wrote is undone like any other register change.
+    assert!(computer.step_back());
+    assert!(computer.step_back());
+    assert_eq!(register(&computer, RegisterName::AX), 0x3000);
+}
+
+#[test]
+fn journal_limits() {
+    let program = assemble("bits 16\nmov ax, 1\nmov ax, 2\nmov ax, 3\nmov ax, 4").unwrap();
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.enable_journal(2, usize::MAX);
+    computer.simulate().unwrap();
+    assert_eq!(computer.journal_length(), 2);
+
+    // The REP STOSW records 2000 bytes of writes, far over the budget, so it
+    // and everything before it is dropped.
+    let program =
+        assemble("bits 16\nmov di, 0x1000\nmov cx, 1000\nrep stosw\nmov ax, 1\nmov ax, 2").unwrap();
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.enable_journal(1000, 4096);
+    computer.simulate().unwrap();
+    assert_eq!(computer.journal_length(), 2);
+    assert!(computer.step_back());
+    assert!(computer.step_back());
+    assert!(!computer.step_back());
+    assert_eq!(register(&computer, RegisterName::AX), 0);
+}
+
+// Stands in for a pipe whose reader has gone away.
+struct ClosedPipe;
+
+impl std::io::Write for ClosedPipe {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_write_failure() {
+    let program = assemble("bits 16\nmov ax, 1\nmov ax, 2").unwrap();
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(ClosedPipe)));
+    match computer.simulate() {
+        Err(SimulationError::Trace(error)) => {
+            assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe)
+        }
+        other => panic!("expected a trace error, got {:?}", other),
+    }
+    // The first instruction ran before its trace failed to write.
+    assert_eq!(computer.instruction_count(), 1);
+    assert_eq!(register(&computer, RegisterName::AX), 1);
+}
+
+#[test]
+fn program_output_failure() {
+    let program = assemble("bits 16\nmov ah, 2\nmov dl, 'A'\nint 0x21").unwrap();
+    let mut computer = Hack86::new(program, CpuModel::Intel8086);
+    computer.set_trace_sink(Box::new(TextTrace::new(std::io::sink())));
+    computer.set_interrupt_handler(Box::new(DosServices::new(ClosedPipe)));
+    match computer.simulate() {
+        Err(SimulationError::Output(error)) => {
+            assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe)
+        }
+        other => panic!("expected an output error, got {:?}", other),
+    }
+}